@@ -0,0 +1,135 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::exit;
+
+use log::info;
+
+use graphics::export::{self, ExportFormat};
+use graphics::resource_pack::{DEFAULT_RESOURCE_PACK, ResourcePack};
+use math::point::Coord2DI;
+use world::chunk::VanillaGenerator;
+use world::material::RenderLayer;
+use world::mesher::mesh_chunk;
+use world::storage::WorldStorage;
+use world::world::World;
+
+const USAGE: &str = "Usage: worldexport (--seed <seed> | --world <dir>) [--radius <chunks>] [--center <x>,<z>] [--output <file.glb|file.obj>] [--resource-pack <dir|file.zip>]...
+
+Exports the chunks within <radius> of <center> as a textured model for other tools. The format
+follows the output's extension: .glb embeds the textures, .obj writes an .mtl and the textures
+next to it.";
+
+enum Source {
+    Seed(u32),
+    World(WorldStorage),
+}
+
+struct Options {
+    source: Source,
+    radius: i32,
+    center: Coord2DI,
+    output: PathBuf,
+    format: ExportFormat,
+    resource_packs: Vec<PathBuf>,
+}
+
+fn parse_args() -> Result<Options, Box<dyn Error>> {
+    let mut source = None;
+    let mut radius = 2;
+    let mut center = Coord2DI::new(0, 0);
+    let mut output = PathBuf::from("terrain.glb");
+    let mut resource_packs = vec![PathBuf::from(DEFAULT_RESOURCE_PACK)];
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("Missing value for {}", arg));
+        match arg.as_str() {
+            "--seed" => source = Some(Source::Seed(value()?.parse()?)),
+            "--world" => source = Some(Source::World(WorldStorage::new(value()?))),
+            "--radius" => radius = value()?.parse()?,
+            "--center" => {
+                let value = value()?;
+                let (x, z) = value.split_once(',').ok_or("Center must be written as <x>,<z>")?;
+                center = Coord2DI::new(x.trim().parse()?, z.trim().parse()?);
+            }
+            "--output" => output = PathBuf::from(value()?),
+            "--resource-pack" => resource_packs.push(PathBuf::from(value()?)),
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            _ => return Err(format!("Unknown argument {}", arg).into()),
+        }
+    }
+
+    if radius < 0 {
+        return Err("Radius must not be negative".into());
+    }
+    let format = ExportFormat::from_path(&output).ok_or("Output must end in .glb or .obj")?;
+
+    Ok(Options {
+        source: source.ok_or("Either --seed or --world is required")?,
+        radius,
+        center,
+        output,
+        format,
+        resource_packs,
+    })
+}
+
+fn load_world(options: &Options) -> Result<World, Box<dyn Error>> {
+    let mut world = World::new();
+    match &options.source {
+        Source::Seed(seed) => world.generate_around(&VanillaGenerator::new(*seed), options.center, options.radius),
+        Source::World(storage) => {
+            if !storage.exists() {
+                return Err(format!("No saved world found at {}", storage.dir().display()).into());
+            }
+            for x in options.center.x - options.radius..=options.center.x + options.radius {
+                for z in options.center.y - options.radius..=options.center.y + options.radius {
+                    if let Some(chunk) = storage.load_chunk(Coord2DI::new(x, z))? {
+                        world.insert_chunk(chunk);
+                    }
+                }
+            }
+        }
+    }
+    Ok(world)
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    let pack = ResourcePack::load(&options.resource_packs)?;
+    let world = load_world(options)?;
+
+    let layers = pack.layers();
+    let meshes: Vec<_> = world.chunks().map(|chunk| mesh_chunk(&world, chunk.pos, &layers)).collect();
+    let block_maps: Vec<_> = meshes.iter()
+        .flat_map(|mesh| [
+            (RenderLayer::Opaque, &mesh.opaque),
+            (RenderLayer::Cutout, &mesh.cutout),
+            (RenderLayer::Translucent, &mesh.translucent),
+            (RenderLayer::Translucent, &mesh.fluid),
+        ])
+        .collect();
+
+    export::export(&options.output, options.format, &block_maps, pack.textures())?;
+    info!("Exported {} chunks to {}", meshes.len(), options.output.display());
+    Ok(())
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            exit(2);
+        }
+    };
+
+    if let Err(e) = run(&options) {
+        eprintln!("Failed to export: {}", e);
+        exit(1);
+    }
+}
@@ -0,0 +1,234 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::process::exit;
+
+use serde_json::json;
+
+use math::{CHUNK_HEIGHT, CHUNK_SIZE};
+use math::point::{Coord2DI, Indexable};
+use world::chunk::Chunk;
+use world::clock::DAY_LENGTH;
+use world::storage::WorldStorage;
+
+const USAGE: &str = "Usage: worldinfo <world dir> [--chunks] [--dump <x>,<z>]
+
+Reports block, height and storage statistics for a saved world.
+  --chunks         also list statistics for every chunk
+  --dump <x>,<z>   print a single chunk as JSON instead";
+
+// Width of a bucket in the surface height histogram
+const HEIGHT_BUCKET: i32 = 16;
+
+struct Options {
+    storage: WorldStorage,
+    per_chunk: bool,
+    dump: Option<Coord2DI>,
+}
+
+fn parse_args() -> Result<Options, Box<dyn Error>> {
+    let mut dir = None;
+    let mut per_chunk = false;
+    let mut dump = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--chunks" => per_chunk = true,
+            "--dump" => {
+                let value = args.next().ok_or("Missing value for --dump")?;
+                let (x, z) = value.split_once(',').ok_or("Chunk must be written as <x>,<z>")?;
+                dump = Some(Coord2DI::new(x.trim().parse()?, z.trim().parse()?));
+            }
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            _ if dir.is_none() && !arg.starts_with("--") => dir = Some(arg),
+            _ => return Err(format!("Unknown argument {}", arg).into()),
+        }
+    }
+
+    Ok(Options {
+        storage: WorldStorage::new(dir.ok_or("A world directory is required")?),
+        per_chunk,
+        dump,
+    })
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024. && unit < UNITS.len() - 1 {
+        value /= 1024.;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+fn dump(storage: &WorldStorage, pos: Coord2DI) -> Result<(), Box<dyn Error>> {
+    let chunk = storage.load_chunk(pos)?.ok_or_else(|| format!("Chunk {} {} has not been saved", pos.x, pos.y))?;
+
+    let mut blocks: Vec<_> = chunk.blocks().iter().collect();
+    blocks.sort_by_key(|block| block.pos.index());
+
+    let json = json!({
+        "pos": [chunk.pos.x, chunk.pos.y],
+        "blocks": blocks.iter()
+            .map(|block| json!({
+                "pos": [block.pos.x, block.pos.y, block.pos.z],
+                "block": block.material.get_name(),
+            }))
+            .collect::<Vec<_>>(),
+    });
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer(&mut stdout, &json)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+/**
+ * Heights of the top block of every non-empty column.
+ */
+fn surface_heights(chunk: &Chunk) -> Vec<i32> {
+    let mut heights = vec![];
+    for z in 0..CHUNK_SIZE as i32 {
+        for x in 0..CHUNK_SIZE as i32 {
+            if let Some(block) = chunk.top_block(x, z) {
+                heights.push(block.pos.y);
+            }
+        }
+    }
+    heights
+}
+
+fn report(options: &Options) -> Result<(), Box<dyn Error>> {
+    let storage = &options.storage;
+    let level = storage.load_level()?;
+
+    let mut positions = storage.chunk_positions()?;
+    positions.sort_by_key(|pos| (pos.y, pos.x));
+    let pending = storage.pending_chunk_positions()?;
+
+    let mut block_counts: BTreeMap<&str, u64> = BTreeMap::new();
+    let mut histogram = vec![0u64; (CHUNK_HEIGHT as i32 / HEIGHT_BUCKET) as usize];
+    let mut corrupt = vec![];
+    let mut disk_size = 0;
+    let mut memory_size = 0;
+
+    if options.per_chunk {
+        println!("{:>6} {:>6} {:>8} {:>5} {:>5} {:>7} {:>10} {:>10}", "x", "z", "blocks", "min", "max", "mean", "disk", "memory");
+    }
+
+    for &pos in &positions {
+        let file_size = fs::metadata(storage.chunk_path(pos)).map(|m| m.len()).unwrap_or(0);
+        disk_size += file_size;
+
+        let chunk = match storage.load_chunk(pos) {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => continue,
+            Err(e) => {
+                corrupt.push((pos, e.to_string()));
+                continue;
+            }
+        };
+        memory_size += chunk.memory_usage() as u64;
+
+        for block in chunk.blocks() {
+            *block_counts.entry(block.material.get_name()).or_default() += 1;
+        }
+
+        let heights = surface_heights(&chunk);
+        for &height in &heights {
+            histogram[(height / HEIGHT_BUCKET) as usize] += 1;
+        }
+
+        if options.per_chunk {
+            let min = heights.iter().min().map_or("-".to_string(), i32::to_string);
+            let max = heights.iter().max().map_or("-".to_string(), i32::to_string);
+            let mean = if heights.is_empty() {
+                "-".to_string()
+            } else {
+                format!("{:.1}", heights.iter().sum::<i32>() as f64 / heights.len() as f64)
+            };
+            println!(
+                "{:>6} {:>6} {:>8} {:>5} {:>5} {:>7} {:>10} {:>10}",
+                pos.x, pos.y, chunk.blocks().len(), min, max, mean,
+                format_bytes(file_size), format_bytes(chunk.memory_usage() as u64),
+            );
+        }
+    }
+
+    if options.per_chunk {
+        println!();
+    }
+
+    println!("World: {}", storage.dir().display());
+    println!("Seed: {}", level.seed);
+    println!("Time: day {}, {} ticks into the day", level.time / DAY_LENGTH, level.time % DAY_LENGTH);
+    println!("Chunks: {} saved ({} corrupt), {} unsaved", positions.len(), corrupt.len(), pending.len());
+    println!("Size: {} on disk, {} in memory", format_bytes(disk_size), format_bytes(memory_size));
+
+    let total_blocks: u64 = block_counts.values().sum();
+    println!("\nBlocks ({} total):", total_blocks);
+    let mut counts: Vec<_> = block_counts.into_iter().collect();
+    counts.sort_by_key(|&(_, count)| Reverse(count));
+    for (name, count) in counts {
+        println!("  {:<28} {:>10} {:>6.1}%", name, count, count as f64 * 100. / total_blocks.max(1) as f64);
+    }
+
+    let columns: u64 = histogram.iter().sum();
+    let widest = histogram.iter().copied().max().unwrap_or(0).max(1);
+    println!("\nSurface heights ({} columns):", columns);
+    for (bucket, &count) in histogram.iter().enumerate().rev() {
+        if count == 0 {
+            continue;
+        }
+        let start = bucket as i32 * HEIGHT_BUCKET;
+        let bar = "#".repeat((count * 40 / widest) as usize);
+        println!("  {:>3}-{:<3} {:<40} {}", start, start + HEIGHT_BUCKET - 1, bar, count);
+    }
+
+    if !corrupt.is_empty() {
+        println!("\nCorrupt chunks:");
+        for (pos, error) in &corrupt {
+            println!("  {} {}: {}", pos.x, pos.y, error);
+        }
+    }
+    if !pending.is_empty() {
+        println!("\nUnsaved chunks (interrupted saves):");
+        for pos in &pending {
+            println!("  {} {}", pos.x, pos.y);
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            exit(2);
+        }
+    };
+
+    if !options.storage.exists() {
+        eprintln!("No saved world found at {}", options.storage.dir().display());
+        exit(1);
+    }
+
+    let result = match options.dump {
+        Some(pos) => dump(&options.storage, pos),
+        None => report(&options),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
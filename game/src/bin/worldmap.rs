@@ -0,0 +1,236 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::thread;
+
+use image::{Rgba, RgbaImage};
+use log::{info, warn};
+
+use graphics::resource_pack::{self, DEFAULT_RESOURCE_PACK, ResourcePack};
+use world::material::TextureLayers;
+use math::{CHUNK_HEIGHT, CHUNK_SIZE};
+use math::face::Face;
+use math::point::Coord2DI;
+use world::chunk::{Chunk, ChunkGenerator, VanillaGenerator};
+use world::storage::WorldStorage;
+
+const USAGE: &str = "Usage: worldmap (--seed <seed> | --world <dir>) [--radius <chunks>] [--center <x>,<z>] [--tile <chunks>] [--output <file.png>] [--resource-pack <dir|file.zip>]...
+
+Renders a top-down map of the chunks within <radius> of <center>, one pixel per block.
+Maps wider than <tile> chunks are split into <output>_<column>_<row>.png tiles.";
+
+enum Source {
+    Seed(u32),
+    World(WorldStorage),
+}
+
+struct Options {
+    source: Source,
+    radius: i32,
+    center: Coord2DI,
+    tile: i32,
+    output: PathBuf,
+    // Blocks are coloured after the textures of these packs, stacked in order
+    resource_packs: Vec<PathBuf>,
+}
+
+fn parse_args() -> Result<Options, Box<dyn Error>> {
+    let mut source = None;
+    let mut radius = 8;
+    let mut center = Coord2DI::new(0, 0);
+    let mut tile = 32;
+    let mut output = PathBuf::from("map.png");
+    let mut resource_packs = vec![PathBuf::from(DEFAULT_RESOURCE_PACK)];
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("Missing value for {}", arg));
+        match arg.as_str() {
+            "--seed" => source = Some(Source::Seed(value()?.parse()?)),
+            "--world" => source = Some(Source::World(WorldStorage::new(value()?))),
+            "--radius" => radius = value()?.parse()?,
+            "--center" => {
+                let value = value()?;
+                let (x, z) = value.split_once(',').ok_or("Center must be written as <x>,<z>")?;
+                center = Coord2DI::new(x.trim().parse()?, z.trim().parse()?);
+            }
+            "--tile" => tile = value()?.parse()?,
+            "--output" => output = PathBuf::from(value()?),
+            "--resource-pack" => resource_packs.push(PathBuf::from(value()?)),
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            _ => return Err(format!("Unknown argument {}", arg).into()),
+        }
+    }
+
+    if radius < 0 || tile < 1 {
+        return Err("Radius must not be negative and tiles must hold at least one chunk".into());
+    }
+
+    Ok(Options {
+        source: source.ok_or("Either --seed or --world is required")?,
+        radius,
+        center,
+        tile,
+        output,
+        resource_packs,
+    })
+}
+
+/**
+ * The top block of every column in a chunk as (texture layer, height), ordered by z then x.
+ */
+type Columns = Vec<Option<(u32, i32)>>;
+
+fn columns(chunk: &Chunk, layers: &TextureLayers) -> Columns {
+    let mut columns = Vec::with_capacity(CHUNK_SIZE * CHUNK_SIZE);
+    for z in 0..CHUNK_SIZE as i32 {
+        for x in 0..CHUNK_SIZE as i32 {
+            columns.push(chunk.top_block(x, z).map(|block| (layers.face_layer(&block.material, Face::Up), block.pos.y)));
+        }
+    }
+    columns
+}
+
+fn load_columns(source: &Source, generator: &VanillaGenerator, positions: &[Coord2DI], layers: &TextureLayers) -> Vec<Option<Columns>> {
+    // Saved worlds only hold the chunks a player visited, the rest are generated from the world's seed
+    let load = |pos: Coord2DI| match source {
+        Source::Seed(_) => Some(columns(&generator.generate_chunk(pos), layers)),
+        Source::World(storage) => match storage.load_chunk(pos) {
+            Ok(chunk) => Some(columns(&chunk.unwrap_or_else(|| generator.generate_chunk(pos)), layers)),
+            Err(e) => {
+                warn!("Failed to load chunk {} {}: {}", pos.x, pos.y, e);
+                None
+            }
+        },
+    };
+
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let per_thread = positions.len().div_ceil(threads).max(1);
+    thread::scope(|scope| {
+        let handles: Vec<_> = positions.chunks(per_thread)
+            .map(|batch| {
+                let load = &load;
+                scope.spawn(move || batch.iter().map(|&pos| load(pos)).collect::<Vec<_>>())
+            })
+            .collect();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+fn shade(color: [u8; 3], height: i32, north_height: i32) -> Rgba<u8> {
+    // Slopes facing north light up and the ones facing away darken, like a hillshade lit from the north
+    let slope = match height.cmp(&north_height) {
+        std::cmp::Ordering::Greater => 1.15,
+        std::cmp::Ordering::Less => 0.8,
+        std::cmp::Ordering::Equal => 1.0,
+    };
+    let altitude = 0.75 + 0.5 * height as f32 / CHUNK_HEIGHT as f32;
+    let factor = slope * altitude;
+
+    Rgba([
+        (color[0] as f32 * factor).min(255.) as u8,
+        (color[1] as f32 * factor).min(255.) as u8,
+        (color[2] as f32 * factor).min(255.) as u8,
+        255,
+    ])
+}
+
+fn tile_path(output: &Path, column: i32, row: i32) -> PathBuf {
+    let stem = output.file_stem().and_then(|s| s.to_str()).unwrap_or("map");
+    output.with_file_name(format!("{}_{}_{}.png", stem, column, row))
+}
+
+fn render(options: &Options, generator: &VanillaGenerator, layers: &TextureLayers, colors: &[[u8; 3]]) -> Result<(), Box<dyn Error>> {
+    let diameter = options.radius * 2 + 1;
+    let tiles = (diameter + options.tile - 1) / options.tile;
+    let min = Coord2DI::new(options.center.x - options.radius, options.center.y - options.radius);
+    let size = CHUNK_SIZE as i32;
+
+    for row in 0..tiles {
+        for column in 0..tiles {
+            let width = options.tile.min(diameter - column * options.tile);
+            let height = options.tile.min(diameter - row * options.tile);
+            let origin = Coord2DI::new(min.x + column * options.tile, min.y + row * options.tile);
+
+            // One extra row of chunks above the tile gives its top pixels a northern neighbour to shade against
+            let positions: Vec<Coord2DI> = (-1..height)
+                .flat_map(|z| (0..width).map(move |x| Coord2DI::new(origin.x + x, origin.y + z)))
+                .collect();
+            let chunks = load_columns(&options.source, generator, &positions, layers);
+
+            // Gather every column into one grid so shading can look across chunk borders
+            let (pixels_x, pixels_z) = ((width * size) as u32, ((height + 1) * size) as u32);
+            let mut grid: Vec<Option<(u32, i32)>> = vec![None; (pixels_x * pixels_z) as usize];
+            for (i, chunk) in chunks.iter().enumerate() {
+                let Some(chunk) = chunk else { continue };
+                let (chunk_x, chunk_z) = (i as i32 % width, i as i32 / width);
+                for (j, column) in chunk.iter().enumerate() {
+                    let x = chunk_x * size + j as i32 % size;
+                    let z = chunk_z * size + j as i32 / size;
+                    grid[(z as u32 * pixels_x + x as u32) as usize] = *column;
+                }
+            }
+
+            let image = RgbaImage::from_fn(pixels_x, pixels_z - size as u32, |x, z| {
+                let z = z + size as u32;
+                match grid[(z * pixels_x + x) as usize] {
+                    Some((texture, height)) => {
+                        let north = grid[((z - 1) * pixels_x + x) as usize];
+                        let color = colors[texture as usize];
+                        shade(color, height, north.map_or(height, |(_, h)| h))
+                    }
+                    None => Rgba([0, 0, 0, 0]),
+                }
+            });
+
+            let path = if tiles == 1 { options.output.clone() } else { tile_path(&options.output, column, row) };
+            image.save(&path)?;
+            info!("Wrote {}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            exit(2);
+        }
+    };
+
+    let generator = match &options.source {
+        Source::Seed(seed) => VanillaGenerator::new(*seed),
+        Source::World(storage) => {
+            if !storage.exists() {
+                eprintln!("No saved world found at {}", storage.dir().display());
+                exit(1);
+            }
+            let level = storage.load_level().unwrap_or_else(|e| {
+                eprintln!("Failed to load level: {}", e);
+                exit(1);
+            });
+            VanillaGenerator::new(level.seed)
+        }
+    };
+
+    let pack = ResourcePack::load(&options.resource_packs).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1);
+    });
+    let colors: Vec<[u8; 3]> = pack.textures().iter()
+        .map(|texture| resource_pack::average_color(&texture.image))
+        .collect();
+
+    if let Err(e) = render(&options, &generator, &pack.layers(), &colors) {
+        eprintln!("Failed to render map: {}", e);
+        exit(1);
+    }
+}
@@ -1,9 +1,179 @@
-use log::info;
+use std::path::PathBuf;
+use std::process::exit;
+
+use log::{error, info};
+
+use graphics::CameraPose;
+use graphics::settings::{FogMode, Settings, Tonemapper};
+use math::point::Point2DI;
+use math::seed::Seed;
+use world::chunk::{ChunkGenerator, VanillaGenerator};
+use world::clock::WorldClock;
+use world::storage::{Level, WorldStorage};
+use world::world::World;
+
+const SAVE_DIRECTORY: &str = "saves/world";
+
+const USAGE: &str = "Usage: game [--time <ticks|sunrise|noon|sunset|midnight>] [--render-distance <chunks>] [--fog <off|linear|exponential>] [--shadows <on|off>] [--bloom <on|off>] [--tonemap <off|aces|agx>] [--gamma <on|off>] [--fxaa <on|off>] [--vignette <on|off>] [--resource-pack <dir|file.zip>]... [--anisotropy <1|2|4|8|16>] [--hot-reload <on|off>] [--cave-culling <on|off>] [--screenshot <file.png> [--size <width>x<height>] [--camera <x,y,z,yaw,pitch>]]
+
+Resource packs are stacked on top of the default one in the order given, each replacing the
+textures, block models and shaders of those below it. F5 reloads them while playing, and with
+--hot-reload on they reload by themselves whenever their files or the game's own shader sources
+change.";
+
+// Size of screenshots rendered without a window, unless given
+const DEFAULT_SCREENSHOT_SIZE: (u32, u32) = (1280, 720);
+
+struct Options {
+    // Time of day to start at
+    time: Option<u64>,
+    settings: Settings,
+    // Renders a single frame to this file without opening a window, then exits
+    screenshot: Option<PathBuf>,
+    size: (u32, u32),
+    camera: CameraPose,
+}
+
+fn parse_size(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = value.split_once('x')?;
+    let size = (width.parse().ok()?, height.parse().ok()?);
+    (size.0 > 0 && size.1 > 0).then_some(size)
+}
+
+fn parse_toggle(arg: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("Invalid value {} for {}, expected on or off", value, arg)),
+    }
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut time = None;
+    let mut settings = Settings::default();
+    let mut screenshot = None;
+    let mut size = DEFAULT_SCREENSHOT_SIZE;
+    let mut camera = CameraPose::default();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        // Only flags that take a value read one, so the next flag isn't taken for it
+        let mut value = || args.next().ok_or_else(|| format!("Missing value for {}", arg));
+        match arg.as_str() {
+            "--time" => {
+                let value = value()?;
+                time = Some(WorldClock::parse_time_of_day(&value).ok_or_else(|| format!("Invalid time of day {}", value))?);
+            }
+            "--render-distance" => {
+                let value = value()?;
+                settings.render_distance = value.parse().map_err(|_| format!("Invalid render distance {}", value))?;
+            }
+            "--fog" => {
+                let value = value()?;
+                settings.fog = FogMode::from_name(&value).ok_or_else(|| format!("Invalid fog mode {}", value))?;
+            }
+            "--shadows" => settings.shadows = parse_toggle(&arg, &value()?)?,
+            "--bloom" => settings.bloom = parse_toggle(&arg, &value()?)?,
+            "--tonemap" => {
+                let value = value()?;
+                settings.tonemapper = Tonemapper::from_name(&value).ok_or_else(|| format!("Invalid tonemapper {}", value))?;
+            }
+            "--gamma" => settings.gamma = parse_toggle(&arg, &value()?)?,
+            "--fxaa" => settings.fxaa = parse_toggle(&arg, &value()?)?,
+            "--vignette" => settings.vignette = parse_toggle(&arg, &value()?)?,
+            "--resource-pack" => settings.resource_packs.push(PathBuf::from(value()?)),
+            "--hot-reload" => settings.hot_reload = parse_toggle(&arg, &value()?)?,
+            "--cave-culling" => settings.cave_culling = parse_toggle(&arg, &value()?)?,
+            "--anisotropy" => {
+                let value = value()?;
+                settings.anisotropy = Settings::parse_anisotropy(&value).ok_or_else(|| format!("Invalid anisotropy {}", value))?;
+            }
+            "--screenshot" => screenshot = Some(PathBuf::from(value()?)),
+            "--size" => {
+                let value = value()?;
+                size = parse_size(&value).ok_or_else(|| format!("Invalid size {}", value))?;
+            }
+            "--camera" => {
+                let value = value()?;
+                camera = CameraPose::parse(&value).ok_or_else(|| format!("Invalid camera {}", value))?;
+            }
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            _ => return Err(format!("Unknown argument {}", arg)),
+        }
+    }
+
+    if settings.render_distance == 0 {
+        return Err("Render distance must be at least one chunk".to_string());
+    }
+
+    Ok(Options {
+        time,
+        settings,
+        screenshot,
+        size,
+        camera,
+    })
+}
 
 fn main() {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
+    let options = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        exit(2);
+    });
+
     info!("Starting game...");
 
-    pollster::block_on(graphics::run());
+    let storage = WorldStorage::new(SAVE_DIRECTORY);
+    let (seed, mut world) = if storage.exists() {
+        info!("Loading world from {}", SAVE_DIRECTORY);
+        let level = storage.load_level().expect("Failed to load level");
+        (level.seed, storage.load_world().expect("Failed to load world"))
+    } else {
+        let seed = Seed::random().get();
+        info!("Generating world with seed {}", seed);
+
+        let mut world = World::new();
+        world.insert_chunk(VanillaGenerator::new(seed).generate_chunk(Point2DI::new(0, 0)));
+
+        let level = Level {
+            seed,
+            time: world.clock.time(),
+        };
+        if let Err(e) = storage.save_level(&level).and_then(|_| storage.save_world(&world)) {
+            error!("Failed to save world: {}", e);
+        }
+        (seed, world)
+    };
+
+    if let Some(time) = options.time {
+        world.clock.set_time_of_day(time);
+    }
+
+    if let Some(path) = options.screenshot {
+        let (width, height) = options.size;
+        let image = pollster::block_on(graphics::render_image(&world, options.settings, width, height, options.camera, false));
+        match image.and_then(|image| image.save(&path).map_err(|e| e.into())) {
+            Ok(()) => info!("Saved screenshot to {}", path.display()),
+            Err(e) => {
+                error!("Failed to render screenshot: {}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    pollster::block_on(graphics::run(world, options.settings, move |world| {
+        let level = Level {
+            seed,
+            time: world.clock.time(),
+        };
+        if let Err(e) = storage.save_level(&level) {
+            error!("Failed to save level: {}", e);
+        }
+    }));
 }
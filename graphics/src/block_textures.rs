@@ -0,0 +1,144 @@
+use std::error::Error;
+use std::num::NonZeroU32;
+
+use wgpu::{BindGroup, BindGroupLayout};
+
+use world::clock::WorldClock;
+
+use crate::graphics::{Graphics, TextureBinding};
+use crate::resource_pack::{Animation, AnimationFrame, ResourcePack};
+use crate::settings::Settings;
+use crate::texture::Texture;
+
+// Sampled in the same stage as the block textures, which is only the shadow map
+const OTHER_SAMPLED_TEXTURES: u32 = 1;
+
+/**
+ * The block textures of a resource pack on the GPU, bound the way the adapter supports, along
+ * with the shader header that declares them and `sample_block`. Animated textures are rewritten
+ * in place as the world clock moves them on to another frame.
+ */
+pub struct BlockTextures {
+    // One texture per block texture, or a single array texture holding them all as layers, kept
+    // for as long as the bind group uses them
+    textures: Vec<Texture>,
+    // Layer of each animated texture and the frame it currently holds
+    animations: Vec<(u32, Animation, AnimationFrame)>,
+    // How the textures are bound, an array texture when there are too many to bind one by one
+    binding: TextureBinding,
+    pub bind_group: BindGroup,
+    pub bind_group_layout: BindGroupLayout,
+    pub shader_header: String,
+}
+
+impl BlockTextures {
+    pub fn new(graphics: &Graphics, pack: &ResourcePack, settings: &Settings) -> Result<Self, Box<dyn Error>> {
+        // Each texture in a binding array counts against the adapter's limit on sampled textures
+        let limit = graphics.device.limits().max_sampled_textures_per_shader_stage;
+        let binding = match graphics.texture_binding {
+            TextureBinding::BindingArray if pack.textures().len() as u32 + OTHER_SAMPLED_TEXTURES > limit => {
+                log::warn!("{} block textures are more than the adapter can bind at once, falling back to an array texture", pack.textures().len());
+                TextureBinding::ArrayTexture
+            }
+            binding => binding,
+        };
+
+        let textures = match binding {
+            TextureBinding::BindingArray => pack.textures().iter()
+                .map(|texture| Texture::from_rgba(&graphics.device, &graphics.queue, &texture.image, settings.anisotropy, &texture.name))
+                .collect::<Result<_, _>>()?,
+            TextureBinding::ArrayTexture => {
+                let images: Vec<_> = pack.textures().iter().map(|texture| &texture.image).collect();
+                vec![Texture::array_from_images(&graphics.device, &graphics.queue, &images, settings.anisotropy, "block_textures")?]
+            }
+        };
+        let (view_dimension, count, shader_header) = match binding {
+            TextureBinding::BindingArray => (
+                wgpu::TextureViewDimension::D2,
+                NonZeroU32::new(textures.len() as u32),
+                pack.shader("block_textures_binding_array.wgsl")?,
+            ),
+            TextureBinding::ArrayTexture => (
+                wgpu::TextureViewDimension::D2Array,
+                None,
+                pack.shader("block_textures_array.wgsl")?,
+            ),
+        };
+
+        let bind_group_layout = graphics.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count,
+                },
+            ],
+            label: Some("texture_bind_group_layout"),
+        });
+
+        let views: Vec<_> = textures.iter().map(|texture| &texture.view).collect();
+        let bind_group = graphics.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&textures[0].sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: match binding {
+                        TextureBinding::BindingArray => wgpu::BindingResource::TextureViewArray(&views),
+                        TextureBinding::ArrayTexture => wgpu::BindingResource::TextureView(views[0]),
+                    },
+                },
+            ],
+            label: Some("diffuse_bind_group"),
+        });
+
+        let animations = pack.textures().iter().enumerate()
+            .filter_map(|(layer, texture)| {
+                let animation = texture.animation.clone()?;
+                let frame = animation.frame_at(0.0);
+                Some((layer as u32, animation, frame))
+            })
+            .collect();
+
+        Ok(Self {
+            textures,
+            animations,
+            binding,
+            bind_group,
+            bind_group_layout,
+            shader_header,
+        })
+    }
+
+    /**
+     * Brings animated textures to the frame they show at the clock's time.
+     */
+    pub fn update(&mut self, graphics: &Graphics, clock: &WorldClock) {
+        for (layer, animation, shown) in &mut self.animations {
+            let frame = animation.frame_at(clock.ticks());
+            if frame == *shown {
+                continue;
+            }
+            let image = animation.image(frame);
+            match self.binding {
+                TextureBinding::BindingArray => self.textures[*layer as usize].write_layer(&graphics.queue, 0, &image),
+                TextureBinding::ArrayTexture => self.textures[0].write_layer(&graphics.queue, *layer, &image),
+            }
+            *shown = frame;
+        }
+    }
+}
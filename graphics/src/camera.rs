@@ -4,24 +4,64 @@ use wgpu::util::DeviceExt;
 use winit::event::{ElementState, MouseScrollDelta, VirtualKeyCode};
 use crate::graphics::Graphics;
 
+/**
+ * Where the camera is and which way it looks, with yaw and pitch in degrees as the controller
+ * keeps them.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraPose {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl Default for CameraPose {
+    // Where a new camera starts
+    fn default() -> Self {
+        Self {
+            position: [0., 0., 1.],
+            yaw: 270.,
+            pitch: 0.,
+        }
+    }
+}
+
+impl CameraPose {
+    /**
+     * Parses `x,y,z,yaw,pitch`.
+     */
+    pub fn parse(value: &str) -> Option<Self> {
+        let values: Vec<f32> = value.split(',').map(|v| v.trim().parse().ok()).collect::<Option<_>>()?;
+        match values[..] {
+            [x, y, z, yaw, pitch] => Some(Self {
+                position: [x, y, z],
+                yaw,
+                pitch,
+            }),
+            _ => None,
+        }
+    }
+}
+
 pub struct Camera {
     pub eye: Point3<f32>,
     pub target: Point3<f32>,
-    up: Vector3<f32>,
+    pub(crate) up: Vector3<f32>,
     pub aspect: f32,
     pub fov: f32,
-    near: f32,
-    far: f32,
+    pub(crate) near: f32,
+    pub far: f32,
     pub controller: CameraController,
     pub global_matrix: Matrix4<f32>,
 }
 
+// Maps OpenGL's -1..1 depth range to wgpu's 0..1, written row by row as nalgebra expects
 #[rustfmt::skip]
-const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
+pub(crate) const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.0,
-    0.0, 0.0, 0.5, 1.0,
+    0.0, 0.0, 0.5, 0.5,
+    0.0, 0.0, 0.0, 1.0,
 );
 
 impl Camera {
@@ -57,7 +97,7 @@ impl Camera {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -90,17 +130,44 @@ impl Camera {
             self.eye.z + self.target.z,
         );
         let projection =
-            Matrix4::new_perspective(self.aspect, self.fov.to_degrees(), self.near, self.far);
+            Matrix4::new_perspective(self.aspect, self.fov.to_radians(), self.near, self.far);
         let view = Matrix4::look_at_rh(&self.eye, &target, &self.up);
         self.global_matrix = OPENGL_TO_WGPU_MATRIX * projection * view;
     }
 
+    /**
+     * The corners of the slice of the view frustum between the `near` and `far` distances
+     * along the view direction, in world space.
+     */
+    pub fn frustum_corners(&self, near: f32, far: f32) -> [Point3<f32>; 8] {
+        let forward = Vector3::new(self.target.x, self.target.y, self.target.z).normalize();
+        let right = forward.cross(&self.up).normalize();
+        let up = right.cross(&forward);
+        let tan_y = (self.fov.to_radians() / 2.).tan();
+        let tan_x = tan_y * self.aspect;
+
+        let mut corners = [self.eye; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let distance = if i < 4 { near } else { far };
+            let x = if i & 1 == 0 { -1. } else { 1. };
+            let y = if i & 2 == 0 { -1. } else { 1. };
+            *corner += (forward + right * x * tan_x + up * y * tan_y) * distance;
+        }
+        corners
+    }
+
+    pub fn set_pose(&mut self, pose: CameraPose) {
+        self.eye = Point3::from(pose.position);
+        self.controller.yaw = pose.yaw;
+        self.controller.pitch = pose.pitch.clamp(-89., 89.);
+    }
+
     pub fn resize(&mut self, graphics: &Graphics) {
         self.aspect = graphics.config.width as f32 / graphics.config.height as f32;
     }
 
     pub fn update(&mut self) {
-        self.fov += self.controller.fov_delta;
+        self.fov = (self.fov + self.controller.fov_delta).clamp(10., 120.);
         self.controller.fov_delta = 0.;
         self.target = Point3::new(
             self.controller.yaw.to_radians().cos() * self.controller.pitch.to_radians().cos(),
@@ -214,16 +281,23 @@ impl CameraController {
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
     view_proj: [[f32; 4]; 4],
+    // Turns screen positions back into world space, e.g. for the view direction of the sky
+    inv_view_proj: [[f32; 4]; 4],
+    eye: [f32; 4],
 }
 
 impl CameraUniform {
     pub fn new() -> Self {
         Self {
             view_proj: Matrix4::identity().into(),
+            inv_view_proj: Matrix4::identity().into(),
+            eye: [0.; 4],
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera) {
         self.view_proj = camera.global_matrix.into();
+        self.inv_view_proj = camera.global_matrix.try_inverse().unwrap_or_else(Matrix4::identity).into();
+        self.eye = [camera.eye.x, camera.eye.y, camera.eye.z, 1.];
     }
 }
@@ -0,0 +1,83 @@
+use std::error::Error;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use image::RgbaImage;
+
+use crate::graphics::Graphics;
+use crate::texture::Texture;
+
+/**
+ * Copies a rendered frame back from the GPU. The texture must hold 8 bit RGBA or BGRA colour.
+ */
+pub fn read_texture(graphics: &Graphics, texture: &Texture) -> Result<RgbaImage, Box<dyn Error>> {
+    let size = texture.texture.size();
+    let format = texture.texture.format();
+    let bgra = match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+        _ => return Err(format!("Can't read back frames in {:?}", format).into()),
+    };
+
+    // Each row of the copy has to start at a multiple of the alignment
+    let row_bytes = size.width * 4;
+    let padded_row_bytes = row_bytes.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let buffer = graphics.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: (padded_row_bytes * size.height) as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = graphics.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        texture.texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(padded_row_bytes),
+                rows_per_image: None,
+            },
+        },
+        size,
+    );
+    graphics.queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    graphics.device.poll(wgpu::Maintain::Wait);
+    receiver.recv()??;
+
+    let mut pixels = Vec::with_capacity((row_bytes * size.height) as usize);
+    for row in slice.get_mapped_range().chunks(padded_row_bytes as usize) {
+        pixels.extend_from_slice(&row[..row_bytes as usize]);
+    }
+    buffer.unmap();
+
+    if bgra {
+        pixels.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+    }
+    // The frame is drawn opaque, whatever alpha the last pass left
+    pixels.chunks_exact_mut(4).for_each(|pixel| pixel[3] = 255);
+
+    RgbaImage::from_raw(size.width, size.height, pixels).ok_or_else(|| "Frame doesn't match its size".into())
+}
+
+/**
+ * Saves a frame into `directory` under a name made from the current time, returning its path.
+ */
+pub fn save_screenshot(image: &RgbaImage, directory: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    std::fs::create_dir_all(directory)?;
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = directory.join(format!("screenshot-{}.png", millis));
+    image.save(&path)?;
+    Ok(path)
+}
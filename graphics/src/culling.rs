@@ -0,0 +1,257 @@
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::ops::AddAssign;
+
+use nalgebra::{Matrix4, Point3, Vector3, Vector4};
+
+use math::point::{Coord2DI, Coord3DI};
+use math::quad::Quad;
+use world::light::NEIGHBOURS;
+use world::visibility::{SECTION_SIZE, SECTIONS};
+use world::world::World;
+
+/**
+ * A box in world space lined up with the axes.
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    /**
+     * A box around the blocks the quads are faces of, or nothing without any quads. Padded by a
+     * block each way, which covers faces however they are turned and the waves on water.
+     */
+    pub fn around<'a>(quads: impl IntoIterator<Item = &'a Quad>) -> Option<Self> {
+        quads.into_iter().fold(None, |bounds: Option<Aabb>, quad| {
+            let pos = Point3::new(quad.pos.x, quad.pos.y, quad.pos.z);
+            let (min, max) = (pos - Vector3::repeat(1.), pos + Vector3::repeat(2.));
+            Some(match bounds {
+                Some(bounds) => Aabb { min: bounds.min.inf(&min), max: bounds.max.sup(&max) },
+                None => Aabb { min, max },
+            })
+        })
+    }
+}
+
+/**
+ * The six planes around everything a camera can see, facing inwards.
+ */
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /**
+     * Pulls the planes out of a view projection matrix with wgpu's depth range of 0 to 1, like
+     * `Camera::global_matrix`.
+     */
+    pub fn from_matrix(matrix: &Matrix4<f32>) -> Self {
+        let row = |i: usize| matrix.row(i).transpose();
+        Self {
+            planes: [
+                row(3) + row(0),
+                row(3) - row(0),
+                row(3) + row(1),
+                row(3) - row(1),
+                row(2),
+                row(3) - row(2),
+            ],
+        }
+    }
+
+    /**
+     * Whether any of the box may be in view. Boxes near the corners of the frustum can pass
+     * without being in view, which only costs drawing them.
+     */
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane's normal
+            let corner = Vector4::new(
+                if plane.x >= 0. { aabb.max.x } else { aabb.min.x },
+                if plane.y >= 0. { aabb.max.y } else { aabb.min.y },
+                if plane.z >= 0. { aabb.max.z } else { aabb.min.z },
+                1.,
+            );
+            plane.dot(&corner) >= 0.
+        })
+    }
+}
+
+/**
+ * A section of a chunk, counted in sections up from the bottom of the world.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SectionPos {
+    pub chunk: Coord2DI,
+    pub y: i32,
+}
+
+impl SectionPos {
+    pub fn of_block(pos: Coord3DI) -> Self {
+        let (chunk, local) = World::to_local(pos);
+        Self { chunk, y: local.y.div_euclid(SECTION_SIZE as i32) }
+    }
+
+    pub fn of_quad(quad: &Quad) -> Self {
+        Self::of_block(quad.block())
+    }
+
+    /**
+     * The box the section's blocks fill, which are centred on whole numbers.
+     */
+    pub fn bounds(self) -> Aabb {
+        let size = SECTION_SIZE as f32;
+        let min = Point3::new(self.chunk.x as f32 * size, self.y as f32 * size, self.chunk.y as f32 * size) - Vector3::repeat(0.5);
+        Aabb { min, max: min + Vector3::repeat(size) }
+    }
+
+    fn neighbour(self, face: usize) -> Self {
+        let [x, y, z] = NEIGHBOURS[face];
+        Self { chunk: Coord2DI::new(self.chunk.x + x, self.chunk.y + z), y: self.y + y }
+    }
+}
+
+/**
+ * The sections the camera may see into, found by walking outwards from its section through the
+ * faces each section connects and never turning back towards the camera. Caves walled off by
+ * solid rock are never reached, and sections out of view are not walked through. From outside
+ * the loaded chunks, like above the world, the walk starts from every edge of them facing the
+ * camera instead.
+ */
+pub fn reachable_sections(world: &World, eye: Point3<f32>, frustum: &Frustum) -> HashSet<SectionPos> {
+    let loaded = |pos: SectionPos| (0..SECTIONS as i32).contains(&pos.y) && world.get_chunk(pos.chunk).is_some();
+
+    // Each section is walked from once, with the face it was entered through and the directions
+    // taken to get there, as bits
+    let mut reached = HashSet::new();
+    let mut queue = VecDeque::new();
+    let camera = SectionPos::of_block(Coord3DI::new(eye.x.round() as i32, eye.y.round() as i32, eye.z.round() as i32));
+    if loaded(camera) {
+        reached.insert(camera);
+        queue.push_back((camera, None, 0u8));
+    } else {
+        for chunk in world.chunks() {
+            for y in 0..SECTIONS as i32 {
+                let pos = SectionPos { chunk: chunk.pos, y };
+                let bounds = pos.bounds();
+                if !frustum.intersects(&bounds) {
+                    continue;
+                }
+                // Faces on the edge of the loaded chunks, with the camera in front of them
+                let facing = (0..NEIGHBOURS.len()).find(|&face| {
+                    let [x, y, z] = NEIGHBOURS[face].map(|n| n as f32);
+                    let normal = Vector3::new(x, y, z);
+                    let plane = if normal.sum() > 0. { bounds.max } else { bounds.min };
+                    !loaded(pos.neighbour(face)) && (eye - plane).dot(&normal) > 0.
+                });
+                if let Some(face) = facing {
+                    reached.insert(pos);
+                    queue.push_back((pos, Some(face), 1 << (face ^ 1)));
+                }
+            }
+        }
+    }
+
+    while let Some((pos, entered, directions)) = queue.pop_front() {
+        let Some(chunk) = world.get_chunk(pos.chunk) else { continue };
+        let visibility = chunk.section_visibility(pos.y as usize);
+        for face in 0..NEIGHBOURS.len() {
+            // Opposite faces differ only in their lowest bit
+            let backwards = face ^ 1;
+            if directions & (1 << backwards) != 0 || entered.is_some_and(|entered| !visibility.connects(entered, face)) {
+                continue;
+            }
+            let next = pos.neighbour(face);
+            if !loaded(next) || reached.contains(&next) || !frustum.intersects(&next.bounds()) {
+                continue;
+            }
+            reached.insert(next);
+            queue.push_back((next, Some(backwards), directions | 1 << face));
+        }
+    }
+    reached
+}
+
+/**
+ * How much of the world culling left out of the last frame. Section meshes are counted once for
+ * each kind of quad a section has, since each is drawn separately.
+ */
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CullingStats {
+    pub meshes: u32,
+    pub visible_meshes: u32,
+    pub quads: u32,
+    pub visible_quads: u32,
+}
+
+impl AddAssign for CullingStats {
+    fn add_assign(&mut self, other: Self) {
+        self.meshes += other.meshes;
+        self.visible_meshes += other.visible_meshes;
+        self.quads += other.quads;
+        self.visible_quads += other.visible_quads;
+    }
+}
+
+impl fmt::Display for CullingStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{} section meshes, {}/{} quads drawn", self.visible_meshes, self.meshes, self.visible_quads, self.quads)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use world::chunk::Chunk;
+    use world::material::STONE;
+
+    use super::*;
+
+    // Sees everything within a thousand blocks of the origin
+    fn everything() -> Frustum {
+        let mut matrix = Matrix4::new_nonuniform_scaling(&Vector3::new(0.001, 0.001, 0.0005));
+        matrix[(2, 3)] = 0.5;
+        Frustum::from_matrix(&matrix)
+    }
+
+    // Stone up to the top of section 3, with a cave hollowed out of section 1 and optionally a
+    // shaft from it up to the surface
+    fn cave_world(shaft: bool) -> World {
+        let mut chunk = Chunk::new(Coord2DI::new(0, 0));
+        let size = SECTION_SIZE as i32;
+        for y in 0..4 * size {
+            for z in 0..size {
+                for x in 0..size {
+                    let cave = (20..28).contains(&y) && (4..12).contains(&x) && (4..12).contains(&z);
+                    let in_shaft = shaft && y >= 20 && x == 8 && z == 8;
+                    if !cave && !in_shaft {
+                        chunk.set_block(Coord3DI::new(x, y, z), Some(STONE));
+                    }
+                }
+            }
+        }
+        let mut world = World::new();
+        world.insert_chunk(chunk);
+        world
+    }
+
+    fn section(y: i32) -> SectionPos {
+        SectionPos { chunk: Coord2DI::new(0, 0), y }
+    }
+
+    #[test]
+    fn sealed_caves_are_not_reached_from_the_surface() {
+        let reached = reachable_sections(&cave_world(false), Point3::new(8., 100., 8.), &everything());
+        assert!(reached.contains(&section(6)));
+        assert!(reached.contains(&section(3)));
+        assert!(!reached.contains(&section(1)));
+    }
+
+    #[test]
+    fn caves_open_to_the_surface_are_reached() {
+        let reached = reachable_sections(&cave_world(true), Point3::new(8., 100., 8.), &everything());
+        assert!(reached.contains(&section(1)));
+    }
+}
@@ -0,0 +1,418 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use nalgebra::{Point3, Vector3};
+use serde_json::{json, Value};
+
+use math::block::block_map::BlockMap;
+use world::material::RenderLayer;
+
+use crate::quad;
+use crate::resource_pack::BlockTexture;
+use crate::texture::ALPHA_CUTOFF;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    // Binary glTF 2.0 with the textures embedded
+    Glb,
+    // Wavefront OBJ with a .mtl file referencing textures written next to it
+    Obj,
+}
+
+impl ExportFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "glb" => Some(ExportFormat::Glb),
+            "obj" => Some(ExportFormat::Obj),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * Triangles sharing a single texture, in world space, and how that texture is drawn.
+ */
+struct Geometry {
+    layer: RenderLayer,
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl Geometry {
+    fn new(layer: RenderLayer) -> Self {
+        Self {
+            layer,
+            positions: vec![],
+            normals: vec![],
+            uvs: vec![],
+            indices: vec![],
+        }
+    }
+}
+
+fn build_geometry(block_maps: &[(RenderLayer, &BlockMap)]) -> BTreeMap<u32, Geometry> {
+    let mut geometry: BTreeMap<u32, Geometry> = BTreeMap::new();
+
+    // Quads from the mesher are already in world space
+    for &(layer, block_map) in block_maps {
+        for quad in block_map.quads() {
+            let group = geometry.entry(quad.texture_index).or_insert_with(|| Geometry::new(layer));
+            let translation = Vector3::new(quad.pos.x, quad.pos.y, quad.pos.z);
+            let normal = quad.rot * Vector3::z();
+            let first = group.positions.len() as u32;
+
+            for vertex in quad::VERTICES {
+                let position = quad.rot * Point3::from(vertex.position) + translation;
+                group.positions.push([position.x, position.y, position.z]);
+                group.normals.push([normal.x, normal.y, normal.z]);
+                group.uvs.push(vertex.tex_coords);
+            }
+            let indices = if quad.flipped { quad::FLIPPED_INDICES } else { quad::INDICES };
+            group.indices.extend(indices.iter().map(|&i| first + i as u32));
+        }
+    }
+
+    geometry
+}
+
+/**
+ * Writes the quads of the given block maps to a glTF binary or OBJ file, chosen by `format`,
+ * each with the layer its quads are drawn in. `textures` are indexed by the quads' texture index,
+ * see `ResourcePack::textures`.
+ */
+pub fn export(path: &Path, format: ExportFormat, block_maps: &[(RenderLayer, &BlockMap)], textures: &[BlockTexture]) -> Result<(), Box<dyn Error>> {
+    let geometry = build_geometry(block_maps);
+    if geometry.is_empty() {
+        return Err("Nothing to export, the chunks have no faces".into());
+    }
+    if let Some(missing) = geometry.keys().find(|&&index| index as usize >= textures.len()) {
+        return Err(format!("No texture for texture index {}", missing).into());
+    }
+
+    match format {
+        ExportFormat::Glb => write_glb(path, &geometry, textures),
+        ExportFormat::Obj => write_obj(path, &geometry, textures),
+    }
+}
+
+fn write_obj(path: &Path, geometry: &BTreeMap<u32, Geometry>, textures: &[BlockTexture]) -> Result<(), Box<dyn Error>> {
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("terrain");
+    let mtl_name = format!("{}.mtl", stem);
+
+    let mut mtl = BufWriter::new(File::create(directory.join(&mtl_name))?);
+    for &index in geometry.keys() {
+        let name = &textures[index as usize].name;
+        let image_name = format!("{}.png", name);
+        fs::write(directory.join(&image_name), textures[index as usize].to_png()?)?;
+
+        writeln!(mtl, "newmtl {}", name)?;
+        writeln!(mtl, "Ka 1.0 1.0 1.0")?;
+        writeln!(mtl, "Kd 1.0 1.0 1.0")?;
+        writeln!(mtl, "Ks 0.0 0.0 0.0")?;
+        writeln!(mtl, "illum 1")?;
+        writeln!(mtl, "map_Kd {}", image_name)?;
+        writeln!(mtl)?;
+    }
+    mtl.flush()?;
+
+    let mut obj = BufWriter::new(File::create(path)?);
+    writeln!(obj, "mtllib {}", mtl_name)?;
+
+    // OBJ indices are global and 1-based
+    let mut base = 1;
+    for (&index, group) in geometry {
+        writeln!(obj, "o {}", textures[index as usize].name)?;
+        writeln!(obj, "usemtl {}", textures[index as usize].name)?;
+        for p in &group.positions {
+            writeln!(obj, "v {} {} {}", p[0], p[1], p[2])?;
+        }
+        for uv in &group.uvs {
+            // OBJ puts the texture origin at the bottom left
+            writeln!(obj, "vt {} {}", uv[0], 1.0 - uv[1])?;
+        }
+        for n in &group.normals {
+            writeln!(obj, "vn {} {} {}", n[0], n[1], n[2])?;
+        }
+        for triangle in group.indices.chunks(3) {
+            let [a, b, c] = [triangle[0] + base, triangle[1] + base, triangle[2] + base];
+            writeln!(obj, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+        base += group.positions.len() as u32;
+    }
+    obj.flush()?;
+
+    Ok(())
+}
+
+fn pad(bytes: &mut Vec<u8>, with: u8) {
+    bytes.resize(bytes.len().next_multiple_of(4), with);
+}
+
+/**
+ * Accumulates the binary chunk of a glb file, keeping every view 4 byte aligned.
+ */
+#[derive(Default)]
+struct BinaryBuffer {
+    data: Vec<u8>,
+    views: Vec<Value>,
+}
+
+impl BinaryBuffer {
+    fn push(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        pad(&mut self.data, 0);
+
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.data.len(),
+            "byteLength": bytes.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+
+        self.data.extend_from_slice(bytes);
+        self.views.push(view);
+        self.views.len() - 1
+    }
+}
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const NEAREST: u32 = 9728;
+
+fn write_glb(path: &Path, geometry: &BTreeMap<u32, Geometry>, textures: &[BlockTexture]) -> Result<(), Box<dyn Error>> {
+    let mut buffer = BinaryBuffer::default();
+    let mut accessors = vec![];
+    let mut primitives = vec![];
+    let mut materials = vec![];
+    let mut images = vec![];
+    let mut gltf_textures = vec![];
+
+    for (material, (&index, group)) in geometry.iter().enumerate() {
+        let name = &textures[index as usize].name;
+        let png = textures[index as usize].to_png()?;
+
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for p in &group.positions {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(p[axis]);
+                max[axis] = max[axis].max(p[axis]);
+            }
+        }
+
+        let positions = buffer.push(bytemuck::cast_slice(&group.positions), Some(ARRAY_BUFFER));
+        let normals = buffer.push(bytemuck::cast_slice(&group.normals), Some(ARRAY_BUFFER));
+        let uvs = buffer.push(bytemuck::cast_slice(&group.uvs), Some(ARRAY_BUFFER));
+        let indices = buffer.push(bytemuck::cast_slice(&group.indices), Some(ELEMENT_ARRAY_BUFFER));
+
+        let first = accessors.len();
+        accessors.push(json!({ "bufferView": positions, "componentType": FLOAT, "count": group.positions.len(), "type": "VEC3", "min": min, "max": max }));
+        accessors.push(json!({ "bufferView": normals, "componentType": FLOAT, "count": group.normals.len(), "type": "VEC3" }));
+        accessors.push(json!({ "bufferView": uvs, "componentType": FLOAT, "count": group.uvs.len(), "type": "VEC2" }));
+        accessors.push(json!({ "bufferView": indices, "componentType": UNSIGNED_INT, "count": group.indices.len(), "type": "SCALAR" }));
+
+        primitives.push(json!({
+            "attributes": { "POSITION": first, "NORMAL": first + 1, "TEXCOORD_0": first + 2 },
+            "indices": first + 3,
+            "material": material,
+        }));
+
+        let image = buffer.push(&png, None);
+        images.push(json!({ "name": name, "bufferView": image, "mimeType": "image/png" }));
+        gltf_textures.push(json!({ "sampler": 0, "source": material }));
+        let mut gltf_material = json!({
+            "name": name,
+            "pbrMetallicRoughness": {
+                "baseColorTexture": { "index": material },
+                "metallicFactor": 0.0,
+                "roughnessFactor": 1.0,
+            },
+        });
+        match group.layer {
+            RenderLayer::Opaque => {}
+            RenderLayer::Cutout => {
+                gltf_material["alphaMode"] = json!("MASK");
+                gltf_material["alphaCutoff"] = json!(ALPHA_CUTOFF as f32 / 255.);
+            }
+            RenderLayer::Translucent => gltf_material["alphaMode"] = json!("BLEND"),
+        }
+        materials.push(gltf_material);
+    }
+
+    let document = json!({
+        "asset": { "version": "2.0", "generator": "wgpu-voxel-game" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "name": "terrain", "mesh": 0 }],
+        "meshes": [{ "name": "terrain", "primitives": primitives }],
+        "accessors": accessors,
+        "materials": materials,
+        "textures": gltf_textures,
+        "images": images,
+        // Nearest filtering keeps the pixel art crisp, like in game
+        "samplers": [{ "magFilter": NEAREST, "minFilter": NEAREST }],
+        "buffers": [{ "byteLength": buffer.data.len() }],
+        "bufferViews": buffer.views,
+    });
+
+    let mut json_chunk = serde_json::to_vec(&document)?;
+    pad(&mut json_chunk, b' ');
+    let mut binary_chunk = buffer.data;
+    pad(&mut binary_chunk, 0);
+
+    let total = 12 + 8 + json_chunk.len() + 8 + binary_chunk.len();
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(b"glTF")?;
+    file.write_all(&2u32.to_le_bytes())?;
+    file.write_all(&(total as u32).to_le_bytes())?;
+    file.write_all(&(json_chunk.len() as u32).to_le_bytes())?;
+    file.write_all(b"JSON")?;
+    file.write_all(&json_chunk)?;
+    file.write_all(&(binary_chunk.len() as u32).to_le_bytes())?;
+    file.write_all(b"BIN\0")?;
+    file.write_all(&binary_chunk)?;
+    file.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use math::point::Coord3DI;
+    use world::material::{DIRT, GLASS, Material, OAK_LEAVES};
+    use world::mesher::mesh_chunk;
+    use world::world::World;
+
+    use crate::resource_pack::{DEFAULT_RESOURCE_PACK, ResourcePack};
+
+    use super::*;
+
+    // Exports every layer of the chunks holding the blocks
+    fn export_blocks(name: &str, format: ExportFormat, blocks: &[(Coord3DI, Material)]) -> PathBuf {
+        let pack = ResourcePack::load(&[Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(DEFAULT_RESOURCE_PACK)]).unwrap();
+        let mut world = World::new();
+        world.set_blocks(blocks.iter().map(|&(pos, material)| (pos, Some(material))));
+        let meshes: Vec<_> = world.chunks().map(|chunk| mesh_chunk(&world, chunk.pos, &pack.layers())).collect();
+        let block_maps: Vec<_> = meshes.iter()
+            .flat_map(|mesh| [(RenderLayer::Opaque, &mesh.opaque), (RenderLayer::Cutout, &mesh.cutout), (RenderLayer::Translucent, &mesh.translucent)])
+            .collect();
+
+        let directory = std::env::temp_dir().join(format!("export-test-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(name);
+        export(&path, format, &block_maps, pack.textures()).unwrap();
+        path
+    }
+
+    // A single dirt block, which has the same texture on all six faces
+    fn export_block(name: &str, format: ExportFormat) -> PathBuf {
+        export_blocks(name, format, &[(Coord3DI::new(3, 10, 5), DIRT)])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> usize {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize
+    }
+
+    fn glb_document(bytes: &[u8]) -> Value {
+        serde_json::from_slice(&bytes[20..20 + u32_at(bytes, 12)]).unwrap()
+    }
+
+    #[test]
+    fn writes_glb() {
+        let path = export_block("block.glb", ExportFormat::Glb);
+        let bytes = fs::read(&path).unwrap();
+
+        assert_eq!(&bytes[0..4], b"glTF");
+        assert_eq!(u32_at(&bytes, 4), 2);
+        assert_eq!(u32_at(&bytes, 8), bytes.len());
+
+        let json_length = u32_at(&bytes, 12);
+        assert_eq!(&bytes[16..20], b"JSON");
+        let document = glb_document(&bytes);
+        let binary = 20 + json_length;
+        assert_eq!(&bytes[binary + 4..binary + 8], b"BIN\0");
+        let binary_length = u32_at(&bytes, binary);
+        assert_eq!(binary + 8 + binary_length, bytes.len());
+
+        let buffer_length = document["buffers"][0]["byteLength"].as_u64().unwrap() as usize;
+        assert!(buffer_length <= binary_length && binary_length - buffer_length < 4);
+        for view in document["bufferViews"].as_array().unwrap() {
+            let end = view["byteOffset"].as_u64().unwrap() + view["byteLength"].as_u64().unwrap();
+            assert!(end as usize <= buffer_length);
+        }
+
+        let [primitive] = document["meshes"][0]["primitives"].as_array().unwrap().as_slice() else { panic!("Expected one primitive") };
+        let count = |accessor: &Value| document["accessors"][accessor.as_u64().unwrap() as usize]["count"].as_u64().unwrap();
+        assert_eq!(count(&primitive["attributes"]["POSITION"]), 6 * 4);
+        assert_eq!(count(&primitive["indices"]), 6 * 6);
+        assert_eq!(document["materials"][0].get("alphaMode"), None);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn writes_obj() {
+        let path = export_block("block.obj", ExportFormat::Obj);
+        let obj = fs::read_to_string(&path).unwrap();
+
+        assert_eq!(obj.lines().filter(|line| line.starts_with("v ")).count(), 6 * 4);
+        assert_eq!(obj.lines().filter(|line| line.starts_with("f ")).count(), 6 * 2);
+        assert!(obj.starts_with("mtllib block.mtl"));
+        assert!(path.with_file_name("dirt.png").exists());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn keeps_blocks_where_they_are_in_the_world() {
+        // Chunk (1, -1)
+        let path = export_blocks("offset.obj", ExportFormat::Obj, &[(Coord3DI::new(19, 10, -11), DIRT)]);
+        let obj = fs::read_to_string(&path).unwrap();
+
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for line in obj.lines().filter_map(|line| line.strip_prefix("v ")) {
+            for (axis, value) in line.split(' ').enumerate() {
+                let value: f32 = value.parse().unwrap();
+                min[axis] = min[axis].min(value);
+                max[axis] = max[axis].max(value);
+            }
+        }
+        assert_eq!((min, max), ([18.5, 9.5, -11.5], [19.5, 10.5, -10.5]));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn sets_the_alpha_mode_of_see_through_blocks() {
+        let path = export_blocks("alpha.glb", ExportFormat::Glb, &[
+            (Coord3DI::new(2, 10, 2), DIRT),
+            (Coord3DI::new(6, 10, 2), OAK_LEAVES),
+            (Coord3DI::new(10, 10, 2), GLASS),
+        ]);
+        let document = glb_document(&fs::read(&path).unwrap());
+
+        let material = |name: &str| document["materials"].as_array().unwrap().iter()
+            .find(|material| material["name"] == name)
+            .unwrap_or_else(|| panic!("No material {}", name))
+            .clone();
+        assert_eq!(material("dirt").get("alphaMode"), None);
+        assert_eq!(material("oak_leaves")["alphaMode"], "MASK");
+        assert!((material("oak_leaves")["alphaCutoff"].as_f64().unwrap() - 0.5).abs() < 0.01);
+        assert_eq!(material("glass")["alphaMode"], "BLEND");
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -1,43 +1,51 @@
+use std::error::Error;
+
 use wgpu::Features;
 
+// Needed to give each block texture its own binding and pick one per fragment
+const BINDING_ARRAY_FEATURES: Features = Features::TEXTURE_BINDING_ARRAY.union(Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING);
+
+/**
+ * How block textures are bound, depending on what the adapter supports.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureBinding {
+    // An array of separate textures, indexed per fragment
+    BindingArray,
+    // The layers of a single array texture, which every adapter can sample
+    ArrayTexture,
+}
+
 pub struct Graphics {
-    pub surface: wgpu::Surface,
+    // None when rendering offscreen, without a window
+    pub surface: Option<wgpu::Surface>,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
+    pub texture_binding: TextureBinding,
 }
 
 impl Graphics {
-    pub async fn new(window: &winit::window::Window) -> Self {
+    pub async fn new(window: &winit::window::Window) -> Result<Self, Box<dyn Error>> {
         let size = window.inner_size();
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            dx12_shader_compiler: Default::default(),
-        });
+        let instance = Self::create_instance();
 
-        let surface = unsafe { instance.create_surface(&window) }.expect("Failed to create surface");
+        let surface = unsafe { instance.create_surface(&window) }?;
 
         let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
             power_preference: wgpu::PowerPreference::HighPerformance,
             compatible_surface: Some(&surface),
             force_fallback_adapter: false,
-        }).await.unwrap();
+        }).await.ok_or("No graphics adapter can draw to the window")?;
 
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                features: Features::TEXTURE_BINDING_ARRAY | Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
-                limits: wgpu::Limits::default(),
-            },
-            None,
-        ).await.unwrap();
+        let (device, queue, texture_binding) = Self::request_device(&adapter).await?;
 
         let surface_capabilities = surface.get_capabilities(&adapter);
+        // Gamma is applied by the last post-processing pass, so it can be turned off
         let surface_format = surface_capabilities.formats.iter()
             .copied()
-            .filter(|f| f.describe().srgb)
-            .next()
+            .find(|f| !f.describe().srgb)
             .unwrap_or(surface_capabilities.formats[0]);
 
         let config = wgpu::SurfaceConfiguration {
@@ -52,12 +60,100 @@ impl Graphics {
 
         surface.configure(&device, &config);
 
-        Self {
-            surface,
+        Ok(Self {
+            surface: Some(surface),
             device,
             queue,
             config,
             size,
+            texture_binding,
+        })
+    }
+
+    /**
+     * Graphics without a window, for rendering frames offscreen and reading them back. Any adapter
+     * will do, falling back to a software one when there is no GPU. With `force_fallback_adapter`
+     * only the software adapter is used, so frames come out the same whatever GPU the machine has.
+     */
+    pub async fn headless(width: u32, height: u32, force_fallback_adapter: bool) -> Result<Self, Box<dyn Error>> {
+        let instance = Self::create_instance();
+
+        let attempts: &[bool] = if force_fallback_adapter { &[true] } else { &[false, true] };
+        let mut adapter = None;
+        for &force_fallback_adapter in attempts {
+            adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: None,
+                force_fallback_adapter,
+            }).await;
+            if adapter.is_some() {
+                break;
+            }
         }
+        let adapter = adapter.ok_or("No graphics adapter found")?;
+
+        let (device, queue, texture_binding) = Self::request_device(&adapter).await?;
+
+        // Without a surface the config only describes the offscreen frames
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        };
+
+        Ok(Self {
+            surface: None,
+            device,
+            queue,
+            config,
+            size: winit::dpi::PhysicalSize::new(width, height),
+            texture_binding,
+        })
+    }
+
+    fn create_instance() -> wgpu::Instance {
+        wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            dx12_shader_compiler: Default::default(),
+        })
+    }
+
+    /**
+     * Asks for a device with only the features and limits the adapter has, choosing how block
+     * textures are bound to match.
+     */
+    async fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue, TextureBinding), wgpu::RequestDeviceError> {
+        let info = adapter.get_info();
+        log::info!("Using {} ({:?}) on {:?}", info.name, info.device_type, info.backend);
+
+        let (features, texture_binding) = if adapter.features().contains(BINDING_ARRAY_FEATURES) {
+            (BINDING_ARRAY_FEATURES, TextureBinding::BindingArray)
+        } else {
+            log::warn!("Adapter can't index texture arrays per fragment, falling back to an array texture");
+            (Features::empty(), TextureBinding::ArrayTexture)
+        };
+        log::info!("Block textures bound as {:?}", texture_binding);
+
+        // Adapters short of the default limits, like those of older and software renderers, get
+        // the lower limits most hardware supports
+        let mut limits = wgpu::Limits::default();
+        if !limits.check_limits(&adapter.limits()) {
+            log::warn!("Adapter doesn't meet the default limits, falling back to downlevel limits");
+            limits = wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits());
+        }
+
+        let (device, queue) = adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features,
+                limits,
+            },
+            None,
+        ).await?;
+        Ok((device, queue, texture_binding))
     }
-}
\ No newline at end of file
+}
@@ -3,6 +3,12 @@
 pub struct InstanceRaw {
     pub(crate) model: [[f32; 4]; 4],
     pub(crate) texture_index: u32,
+    pub(crate) light: u32,
+    pub(crate) ao: u32,
+    // Fluid flow along x and z as signed bytes, then the surface height
+    pub(crate) fluid: u32,
+    // Light level given off by the block
+    pub(crate) emission: u32,
 }
 
 impl InstanceRaw {
@@ -36,6 +42,26 @@ impl InstanceRaw {
                     offset: std::mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
                     shader_location: 7,
                 },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Uint32,
+                    offset: std::mem::size_of::<[f32; 17]>() as wgpu::BufferAddress,
+                    shader_location: 8,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Uint32,
+                    offset: std::mem::size_of::<[f32; 18]>() as wgpu::BufferAddress,
+                    shader_location: 9,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Uint32,
+                    offset: std::mem::size_of::<[f32; 19]>() as wgpu::BufferAddress,
+                    shader_location: 10,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Uint32,
+                    offset: std::mem::size_of::<[f32; 20]>() as wgpu::BufferAddress,
+                    shader_location: 11,
+                },
             ],
         }
     }
@@ -1,32 +1,76 @@
-use std::time::Instant;
+use std::error::Error;
+use std::time::{Duration, Instant};
 
+use image::RgbaImage;
 use winit::{
     event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::{Window, WindowBuilder},
 };
 
+use graphics::Graphics;
 use renderer::Renderer;
+use settings::Settings;
+use world::world::World;
 
 use crate::vertex::Vertex;
 
+pub use camera::CameraPose;
+
 mod renderer;
+pub mod resource_pack;
+mod preprocessor;
+mod watcher;
+mod block_textures;
 mod texture;
 mod graphics;
 mod pipeline;
 mod vertex;
 mod camera;
 mod instance;
+mod sky;
+mod shadow;
+pub mod lights;
+mod translucent;
+mod water;
+mod post;
+mod capture;
+mod culling;
+pub mod settings;
 pub mod quad;
+pub mod export;
 // mod cube;
 
-pub async fn run() {
+const WINDOW_TITLE: &str = "wgpu-voxel-game";
+// How often the window title is brought up to date with what is being drawn
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/**
+ * Opens a window onto the world and runs until it is closed. `on_exit` receives the world with
+ * its clock where the session left it, e.g. to save it.
+ */
+pub async fn run(mut world: World, settings: Settings, on_exit: impl FnOnce(&World) + 'static) {
     let event_loop: EventLoop<()> = EventLoop::new();
-    let window: Window = WindowBuilder::new().build(&event_loop).unwrap();
+    let window: Window = WindowBuilder::new().with_title(WINDOW_TITLE).build(&event_loop).unwrap();
 
-    let mut state: Renderer = Renderer::new(window).await;
+    let graphics = match Graphics::new(&window).await {
+        Ok(graphics) => graphics,
+        Err(e) => {
+            log::error!("Failed to set up graphics: {}", e);
+            return;
+        }
+    };
+    let mut state: Renderer = match Renderer::new(graphics, &world, settings) {
+        Ok(renderer) => renderer,
+        Err(e) => {
+            log::error!("Failed to start the renderer: {}", e);
+            return;
+        }
+    };
 
+    let mut on_exit = Some(on_exit);
     let mut last_render_time = Instant::now();
+    let mut last_stats_time = Instant::now();
     event_loop.run(move |event, _, control_flow| {
         match event {
             Event::DeviceEvent {
@@ -38,7 +82,7 @@ pub async fn run() {
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == state.window().id() => {
+            } if window_id == window.id() => {
                 if !state.input(event) {
                     match event {
                         WindowEvent::CloseRequested
@@ -63,11 +107,15 @@ pub async fn run() {
                 }
             }
 
-            Event::RedrawRequested(window_id) if window_id == state.window().id() => {
+            Event::RedrawRequested(window_id) if window_id == window.id() => {
                 let now = Instant::now();
                 let dt = now - last_render_time;
                 last_render_time = now;
-                state.update(dt);
+                state.update(dt, &world);
+                if now - last_stats_time >= STATS_INTERVAL {
+                    last_stats_time = now;
+                    window.set_title(&format!("{} - {}", WINDOW_TITLE, state.culling_stats()));
+                }
                 match state.render() {
                     Ok(_) => {}
                     // Reconfigure the surface if it's lost or outdated
@@ -79,12 +127,39 @@ pub async fn run() {
                 }
             }
 
+            Event::LoopDestroyed => {
+                world.clock = state.clock;
+                if let Some(on_exit) = on_exit.take() {
+                    on_exit(&world);
+                }
+            }
+
             Event::RedrawEventsCleared => {
                 // RedrawRequested will only trigger once, unless we manually
                 // request it.
-                state.window().request_redraw();
+                window.request_redraw();
             }
             _ => {}
         }
     });
-}
\ No newline at end of file
+}
+
+/**
+ * Renders a single frame of the world offscreen, without a window, as seen from `camera`. See
+ * `Graphics::headless` for `force_fallback_adapter`.
+ */
+pub async fn render_image(
+    world: &World,
+    settings: Settings,
+    width: u32,
+    height: u32,
+    camera: CameraPose,
+    force_fallback_adapter: bool,
+) -> Result<RgbaImage, Box<dyn Error>> {
+    let graphics = Graphics::headless(width, height, force_fallback_adapter).await?;
+    let mut renderer = Renderer::new(graphics, world, settings)?;
+    renderer.set_camera_pose(camera);
+    renderer.update(Duration::ZERO, world);
+    log::debug!("Culling: {}", renderer.culling_stats());
+    renderer.capture()
+}
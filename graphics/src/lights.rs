@@ -0,0 +1,62 @@
+use wgpu::Buffer;
+
+use crate::graphics::Graphics;
+
+// Lights beyond this many are dropped, the closest ones should be passed first
+pub const MAX_POINT_LIGHTS: usize = 32;
+// Light count and padding ahead of the lights, matching `PointLights` in shader.wgsl
+const HEADER_SIZE: usize = 16;
+
+/**
+ * A light that moves with something in the world, like a held torch, lit per fragment rather
+ * than through the block light flood fill.
+ */
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PointLight {
+    pub position: [f32; 3],
+    // Distance in blocks at which the light has faded out
+    pub radius: f32,
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl PointLight {
+    pub fn at(self, position: [f32; 3]) -> Self {
+        Self {
+            position,
+            ..self
+        }
+    }
+}
+
+/**
+ * The point lights of the current frame, in a storage buffer read by the block shader.
+ */
+pub struct PointLights {
+    pub buffer: Buffer,
+}
+
+impl PointLights {
+    pub fn new(graphics: &Graphics) -> Self {
+        let buffer = graphics.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Point Light Buffer"),
+            size: (HEADER_SIZE + MAX_POINT_LIGHTS * std::mem::size_of::<PointLight>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            buffer,
+        }
+    }
+
+    pub fn update(&self, graphics: &Graphics, lights: &[PointLight]) {
+        let lights = &lights[..lights.len().min(MAX_POINT_LIGHTS)];
+        let header = [lights.len() as u32, 0, 0, 0];
+        graphics.queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&header));
+        if !lights.is_empty() {
+            graphics.queue.write_buffer(&self.buffer, HEADER_SIZE as u64, bytemuck::cast_slice(lights));
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use world::material::RenderLayer;
+
 use crate::graphics::Graphics;
 use crate::instance::InstanceRaw;
 use crate::texture::Texture;
@@ -7,6 +9,11 @@ pub struct Pipeline {
 }
 
 impl Pipeline {
+    /**
+     * A pipeline drawing block quads of one render layer. Cutout blocks discard their see-through
+     * pixels in `fs_cutout`, translucent ones blend over what is behind them without writing depth
+     * and are drawn from both sides, so water can be seen from below its surface.
+     */
     pub fn new(
         graphics: &Graphics,
         label: &str,
@@ -14,8 +21,14 @@ impl Pipeline {
         shader_content: &str,
         vertex_layout: wgpu::VertexBufferLayout,
         layout: Option<&wgpu::PipelineLayout>,
+        layer: RenderLayer,
     ) -> Self {
-        // Consider reading shaders at runtime?
+        let (entry_point, blend, cull_mode) = match layer {
+            RenderLayer::Opaque => ("fs_main", wgpu::BlendState::REPLACE, Some(wgpu::Face::Back)),
+            RenderLayer::Cutout => ("fs_cutout", wgpu::BlendState::REPLACE, Some(wgpu::Face::Back)),
+            RenderLayer::Translucent => ("fs_main", wgpu::BlendState::ALPHA_BLENDING, None),
+        };
+
         let shader = graphics.device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some(&format!("{} shader", shader_label)),
             source: wgpu::ShaderSource::Wgsl(shader_content.into()),
@@ -31,10 +44,10 @@ impl Pipeline {
             },
             fragment: Some(wgpu::FragmentState {
                 module: &shader,
-                entry_point: "fs_main",
+                entry_point,
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: graphics.config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
+                    format: Texture::HDR_FORMAT,
+                    blend: Some(blend),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
             }),
@@ -42,7 +55,7 @@ impl Pipeline {
                 topology: wgpu::PrimitiveTopology::TriangleList,
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
+                cull_mode,
                 // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
                 polygon_mode: wgpu::PolygonMode::Fill,
                 // Requires Features::DEPTH_CLIP_CONTROL
@@ -52,7 +65,7 @@ impl Pipeline {
             },
             depth_stencil: Some(wgpu::DepthStencilState {
                 format: Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
+                depth_write_enabled: layer != RenderLayer::Translucent,
                 depth_compare: wgpu::CompareFunction::Less, // 1.
                 stencil: wgpu::StencilState::default(), // 2.
                 bias: wgpu::DepthBiasState::default(),
@@ -69,4 +82,154 @@ impl Pipeline {
             pipeline
         }
     }
-}
\ No newline at end of file
+
+    /**
+     * A pipeline drawing a single triangle over the whole screen, without vertex buffers. It
+     * keeps the depth buffer untouched so it can share a pass with the block pipeline.
+     */
+    pub fn fullscreen(
+        graphics: &Graphics,
+        label: &str,
+        shader_content: &str,
+        layout: Option<&wgpu::PipelineLayout>,
+    ) -> Self {
+        let shader = graphics.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&format!("{} shader", label)),
+            source: wgpu::ShaderSource::Wgsl(shader_content.into()),
+        });
+
+        let pipeline = graphics.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("{} Render Pipeline", label)),
+            layout,
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: Texture::HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            pipeline
+        }
+    }
+
+    /**
+     * A fullscreen pipeline for one step of post-processing, reading the previous step through
+     * its bind group and writing `format` without any depth buffer.
+     */
+    pub fn post(
+        graphics: &Graphics,
+        label: &str,
+        shader_content: &str,
+        entry_point: &str,
+        layout: Option<&wgpu::PipelineLayout>,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let shader = graphics.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&format!("{} shader", label)),
+            source: wgpu::ShaderSource::Wgsl(shader_content.into()),
+        });
+
+        let pipeline = graphics.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("{} Render Pipeline", label)),
+            layout,
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            pipeline
+        }
+    }
+
+    /**
+     * A pipeline that only writes depth, for rendering blocks into a shadow map. Depth is biased
+     * by slope to keep surfaces from shadowing themselves. A `fragment_entry` can discard fragments
+     * that shouldn't cast shadows, without it depth is written for the whole quad.
+     */
+    pub fn depth_only(
+        graphics: &Graphics,
+        label: &str,
+        shader_content: &str,
+        fragment_entry: Option<&str>,
+        vertex_layout: wgpu::VertexBufferLayout,
+        layout: Option<&wgpu::PipelineLayout>,
+    ) -> Self {
+        let shader = graphics.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&format!("{} shader", label)),
+            source: wgpu::ShaderSource::Wgsl(shader_content.into()),
+        });
+
+        let pipeline = graphics.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("{} Render Pipeline", label)),
+            layout,
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[vertex_layout, InstanceRaw::init_buffer_layout()],
+            },
+            fragment: fragment_entry.map(|entry_point| wgpu::FragmentState {
+                module: &shader,
+                entry_point,
+                targets: &[],
+            }),
+            primitive: wgpu::PrimitiveState {
+                // Both sides cast shadows, so thin overhangs don't leak light
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            pipeline
+        }
+    }
+}
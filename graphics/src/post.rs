@@ -0,0 +1,262 @@
+use wgpu::{BindGroup, BindGroupLayout, Buffer, TextureView};
+use wgpu::util::DeviceExt;
+
+use crate::graphics::Graphics;
+use crate::pipeline::Pipeline;
+use crate::settings::{Settings, Tonemapper};
+use crate::texture::Texture;
+
+// Scene brightness is scaled by this before tonemapping
+const EXPOSURE: f32 = 1.0;
+// Only brightness above this spills into the bloom
+const BLOOM_THRESHOLD: f32 = 1.0;
+const BLOOM_STRENGTH: f32 = 0.6;
+// Rounds of horizontal and vertical blur, each widening the glow
+const BLOOM_BLURS: usize = 2;
+const VIGNETTE_STRENGTH: f32 = 0.45;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostUniform {
+    // Exposure, tonemapper and whether to apply gamma
+    tone: [f32; 4],
+    // Bloom threshold and strength, then vignette strength
+    effects: [f32; 4],
+}
+
+/**
+ * The textures passed between the steps, which follow the size of the window.
+ */
+struct Targets {
+    // The scene, before any post-processing
+    hdr: Texture,
+    // Half resolution bright parts of the scene, blurred back and forth between the two
+    bloom: [Texture; 2],
+    // Display range results of the steps after compositing, when more than one of them runs
+    ldr: [Texture; 2],
+    bright_group: BindGroup,
+    composite_group: BindGroup,
+    bloom_groups: [BindGroup; 2],
+    ldr_groups: [BindGroup; 2],
+}
+
+impl Targets {
+    fn new(graphics: &Graphics, layout: &BindGroupLayout, buffer: &Buffer, blank: &Texture) -> Self {
+        let (width, height) = (graphics.config.width, graphics.config.height);
+        let hdr = Texture::create_render_target(&graphics.device, width, height, Texture::HDR_FORMAT, "hdr_texture");
+        let bloom = [0, 1].map(|i| {
+            Texture::create_render_target(&graphics.device, width / 2, height / 2, Texture::HDR_FORMAT, &format!("bloom_texture_{}", i))
+        });
+        let ldr = [0, 1].map(|i| {
+            Texture::create_render_target(&graphics.device, width, height, graphics.config.format, &format!("ldr_texture_{}", i))
+        });
+
+        // A texture can't be read while it's being drawn to, so only compositing sees the bloom
+        let group = |source: &Texture, bloom: &Texture| create_bind_group(graphics, layout, buffer, source, bloom);
+        Self {
+            bright_group: group(&hdr, blank),
+            composite_group: group(&hdr, &bloom[0]),
+            bloom_groups: [group(&bloom[0], blank), group(&bloom[1], blank)],
+            ldr_groups: [group(&ldr[0], blank), group(&ldr[1], blank)],
+            hdr,
+            bloom,
+            ldr,
+        }
+    }
+}
+
+fn create_bind_group(graphics: &Graphics, layout: &BindGroupLayout, buffer: &Buffer, source: &Texture, bloom: &Texture) -> BindGroup {
+    graphics.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&source.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&bloom.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(&source.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: buffer.as_entire_binding(),
+            },
+        ],
+        label: Some("post_bind_group"),
+    })
+}
+
+fn draw_pass(encoder: &mut wgpu::CommandEncoder, label: &str, pipeline: &Pipeline, bind_group: &BindGroup, target: &TextureView) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: None,
+    });
+    render_pass.set_pipeline(&pipeline.pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+}
+
+/**
+ * Turns the HDR scene into the final image: bloom around bright and glowing blocks, tonemapping
+ * and gamma, then optional anti-aliasing and vignette. Steps turned off in the settings are
+ * skipped, and the last one that runs writes straight to the screen.
+ */
+pub struct PostProcess {
+    targets: Targets,
+    // Stands in for the bloom in steps that don't read it
+    blank: Texture,
+    buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    // The surface encodes gamma itself, so the shader must not do it again
+    surface_srgb: bool,
+    bright: Pipeline,
+    blur_horizontal: Pipeline,
+    blur_vertical: Pipeline,
+    composite: Pipeline,
+    fxaa: Pipeline,
+    vignette: Pipeline,
+}
+
+impl PostProcess {
+    pub fn new(graphics: &Graphics, settings: &Settings, shader: &str) -> Self {
+        let surface_srgb = graphics.config.format.describe().srgb;
+        let buffer = graphics.device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Post Buffer"),
+                contents: bytemuck::cast_slice(&[Self::uniform(settings, surface_srgb)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let bind_group_layout = graphics.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("post_bind_group_layout"),
+        });
+
+        let pipeline_layout = graphics.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |label, entry_point, format| {
+            Pipeline::post(graphics, label, shader, entry_point, Some(&pipeline_layout), format)
+        };
+
+        let blank = Texture::create_render_target(&graphics.device, 1, 1, Texture::HDR_FORMAT, "blank_texture");
+        let targets = Targets::new(graphics, &bind_group_layout, &buffer, &blank);
+
+        Self {
+            targets,
+            blank,
+            buffer,
+            bind_group_layout,
+            surface_srgb,
+            bright: pipeline("Bloom Bright", "fs_bright", Texture::HDR_FORMAT),
+            blur_horizontal: pipeline("Bloom Horizontal Blur", "fs_blur_horizontal", Texture::HDR_FORMAT),
+            blur_vertical: pipeline("Bloom Vertical Blur", "fs_blur_vertical", Texture::HDR_FORMAT),
+            composite: pipeline("Composite", "fs_composite", graphics.config.format),
+            fxaa: pipeline("FXAA", "fs_fxaa", graphics.config.format),
+            vignette: pipeline("Vignette", "fs_vignette", graphics.config.format),
+        }
+    }
+
+    fn uniform(settings: &Settings, surface_srgb: bool) -> PostUniform {
+        let tonemapper = match settings.tonemapper {
+            Tonemapper::Off => 0.,
+            Tonemapper::Aces => 1.,
+            Tonemapper::Agx => 2.,
+        };
+        let gamma = settings.gamma && !surface_srgb;
+        let bloom_strength = if settings.bloom { BLOOM_STRENGTH } else { 0. };
+        PostUniform {
+            tone: [EXPOSURE, tonemapper, gamma as u32 as f32, 0.],
+            effects: [BLOOM_THRESHOLD, bloom_strength, VIGNETTE_STRENGTH, 0.],
+        }
+    }
+
+    /**
+     * The texture the scene is drawn into.
+     */
+    pub fn scene_view(&self) -> &TextureView {
+        &self.targets.hdr.view
+    }
+
+    pub fn resize(&mut self, graphics: &Graphics) {
+        self.targets = Targets::new(graphics, &self.bind_group_layout, &self.buffer, &self.blank);
+    }
+
+    pub fn update(&self, graphics: &Graphics, settings: &Settings) {
+        graphics.queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[Self::uniform(settings, self.surface_srgb)]));
+    }
+
+    /**
+     * Runs the enabled steps on the drawn scene, finishing in `output`.
+     */
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, settings: &Settings, output: &TextureView) {
+        let targets = &self.targets;
+        if settings.bloom {
+            draw_pass(encoder, "Bloom Bright Pass", &self.bright, &targets.bright_group, &targets.bloom[0].view);
+            for _ in 0..BLOOM_BLURS {
+                draw_pass(encoder, "Bloom Blur Pass", &self.blur_horizontal, &targets.bloom_groups[0], &targets.bloom[1].view);
+                draw_pass(encoder, "Bloom Blur Pass", &self.blur_vertical, &targets.bloom_groups[1], &targets.bloom[0].view);
+            }
+        }
+
+        let mut steps = vec![("Composite Pass", &self.composite)];
+        if settings.fxaa {
+            steps.push(("FXAA Pass", &self.fxaa));
+        }
+        if settings.vignette {
+            steps.push(("Vignette Pass", &self.vignette));
+        }
+
+        // Each step after compositing reads the texture the step before it wrote
+        let last = steps.len() - 1;
+        for (i, (label, pipeline)) in steps.into_iter().enumerate() {
+            let bind_group = if i == 0 { &targets.composite_group } else { &targets.ldr_groups[(i - 1) % 2] };
+            let target = if i == last { output } else { &targets.ldr[i % 2].view };
+            draw_pass(encoder, label, pipeline, bind_group, target);
+        }
+    }
+}
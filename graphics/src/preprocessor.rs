@@ -0,0 +1,130 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+
+/**
+ * Expands the two directives WGSL lacks, each on a line of its own:
+ * - `#include "name.wgsl"` pastes in another shader, once per shader however often it's included
+ * - `#define NAME value` replaces the identifier `NAME` with `value` on every line after it,
+ *   including those of shaders included later
+ *
+ * Shaders are looked up by name through `source`, so resource packs can replace included ones too.
+ */
+pub fn preprocess<'a>(name: &str, source: impl Fn(&str) -> Option<&'a str>) -> Result<String, Box<dyn Error>> {
+    let mut preprocessor = Preprocessor {
+        source: &source,
+        defines: BTreeMap::new(),
+        included: BTreeSet::new(),
+        output: String::new(),
+    };
+    preprocessor.include(name)?;
+    Ok(preprocessor.output)
+}
+
+struct Preprocessor<'a, 'b> {
+    source: &'b dyn Fn(&str) -> Option<&'a str>,
+    defines: BTreeMap<String, String>,
+    // Shaders already pasted in, which also keeps includes from going round in circles
+    included: BTreeSet<String>,
+    output: String,
+}
+
+impl Preprocessor<'_, '_> {
+    fn include(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        if !self.included.insert(name.to_string()) {
+            return Ok(());
+        }
+        let source = (self.source)(name).ok_or_else(|| format!("No shader named {}", name))?;
+
+        for (number, line) in source.lines().enumerate() {
+            let directive = line.trim_start();
+            if let Some(included) = directive.strip_prefix("#include") {
+                let included = included.trim().strip_prefix('"').and_then(|included| included.strip_suffix('"'))
+                    .ok_or_else(|| format!("{}:{}: Expected #include \"name.wgsl\"", name, number + 1))?;
+                self.include(included).map_err(|e| format!("{}:{}: {}", name, number + 1, e))?;
+            } else if let Some(define) = directive.strip_prefix("#define") {
+                let (key, value) = define.trim().split_once(char::is_whitespace)
+                    .ok_or_else(|| format!("{}:{}: Expected #define NAME value", name, number + 1))?;
+                let value = self.substitute(value.trim());
+                self.defines.insert(key.to_string(), value);
+            } else if directive.starts_with('#') {
+                return Err(format!("{}:{}: Unknown directive {}", name, number + 1, directive).into());
+            } else {
+                let line = self.substitute(line);
+                self.output.push_str(&line);
+                self.output.push('\n');
+            }
+        }
+        Ok(())
+    }
+
+    // Replaces whole identifiers only, so defining `SKY` leaves `SKY_GROUP` alone
+    fn substitute(&self, line: &str) -> String {
+        if self.defines.is_empty() {
+            return line.to_string();
+        }
+        let mut result = String::with_capacity(line.len());
+        let mut rest = line;
+        while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            result.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            let identifier = &rest[..end];
+            result.push_str(self.defines.get(identifier).map_or(identifier, String::as_str));
+            rest = &rest[end..];
+        }
+        result.push_str(rest);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(name: &str, shaders: &[(&'static str, &'static str)]) -> Result<String, Box<dyn Error>> {
+        preprocess(name, |name| shaders.iter().find(|(shader, _)| *shader == name).map(|(_, source)| *source))
+    }
+
+    #[test]
+    fn replaces_whole_identifiers_only() {
+        let output = run("a.wgsl", &[("a.wgsl", "#define SKY 2\n@group(SKY) var sky: SKY_GROUP; // SKY")]).unwrap();
+        assert_eq!(output, "@group(2) var sky: SKY_GROUP; // 2\n");
+    }
+
+    #[test]
+    fn defines_reach_later_includes() {
+        let output = run("main.wgsl", &[
+            ("main.wgsl", "#define GROUP 3\n#include \"camera.wgsl\"\nfn main() {}"),
+            ("camera.wgsl", "@group(GROUP) var<uniform> camera: Camera;"),
+        ]).unwrap();
+        assert_eq!(output, "@group(3) var<uniform> camera: Camera;\nfn main() {}\n");
+    }
+
+    #[test]
+    fn defines_can_use_earlier_defines() {
+        let output = run("a.wgsl", &[("a.wgsl", "#define A 1\n#define B A\nB")]).unwrap();
+        assert_eq!(output, "1\n");
+    }
+
+    #[test]
+    fn includes_each_shader_once() {
+        let output = run("main.wgsl", &[
+            ("main.wgsl", "#include \"a.wgsl\"\n#include \"b.wgsl\"\n#include \"a.wgsl\""),
+            ("a.wgsl", "#include \"b.wgsl\"\na"),
+            ("b.wgsl", "#include \"a.wgsl\"\nb"),
+        ]).unwrap();
+        assert_eq!(output, "b\na\n");
+    }
+
+    #[test]
+    fn reports_where_errors_are() {
+        let error = |shaders| run("main.wgsl", shaders).unwrap_err().to_string();
+        assert_eq!(error(&[("main.wgsl", "fn main() {}\n#pragma once")]), "main.wgsl:2: Unknown directive #pragma once");
+        assert_eq!(error(&[("main.wgsl", "#include missing.wgsl")]), "main.wgsl:1: Expected #include \"name.wgsl\"");
+        assert_eq!(error(&[("main.wgsl", "#define EMPTY")]), "main.wgsl:1: Expected #define NAME value");
+        assert_eq!(
+            error(&[("main.wgsl", "#include \"a.wgsl\""), ("a.wgsl", "\n#include \"missing.wgsl\"")]),
+            "main.wgsl:1: a.wgsl:2: No shader named missing.wgsl",
+        );
+    }
+}
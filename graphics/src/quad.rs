@@ -20,6 +20,12 @@ pub const INDICES: &[u16] = &[
     3, 1, 0
 ];
 
+// Split along the other diagonal, for quads with `flipped` set
+pub const FLIPPED_INDICES: &[u16] = &[
+    0, 2, 1,
+    1, 2, 3
+];
+
 pub trait Raw {
     fn to_raw(&self) -> InstanceRaw;
 }
@@ -31,6 +37,10 @@ impl Raw for Quad {
         InstanceRaw {
             model: t_matrix,
             texture_index: self.texture_index,
+            light: u32::from_le_bytes(self.light),
+            ao: self.ao.iter().enumerate().fold(0, |packed, (corner, &ao)| packed | (ao as u32) << (corner * 2)),
+            fluid: u32::from_le_bytes([self.flow[0] as u8, self.flow[1] as u8, self.surface_height, 0]),
+            emission: self.emission as u32,
         }
     }
 }
\ No newline at end of file
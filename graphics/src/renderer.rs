@@ -1,71 +1,188 @@
-use std::num::NonZeroU32;
+use std::error::Error;
+use std::ops::Range;
+use std::path::Path;
 use std::time::Duration;
 
-use wgpu::BindingResource::TextureViewArray;
-use wgpu::util::DeviceExt;
-use winit::{dpi::PhysicalSize, event::WindowEvent, window::Window};
-use winit::event::{ElementState, KeyboardInput, MouseButton};
-
-use math::block::block_map::BlockMap;
-use math::point::Point2DI;
-use math::seed::Seed;
-use world::chunk::{Chunk, ChunkGenerator, VanillaGenerator};
+use image::RgbaImage;
 
-use crate::camera::{Camera, CameraUniform};
+use wgpu::util::DeviceExt;
+use winit::{dpi::PhysicalSize, event::WindowEvent};
+use winit::event::{ElementState, KeyboardInput, MouseButton, VirtualKeyCode};
+
+use math::CHUNK_SIZE;
+use math::point::{Coord2DI, Coord3DI};
+use math::quad::Quad;
+use world::clock::{DAY_LENGTH, WorldClock};
+use world::material::RenderLayer;
+use world::mesher::mesh_chunk;
+use world::visibility::SECTIONS;
+use world::world::World;
+
+use crate::block_textures::BlockTextures;
+use crate::camera::{Camera, CameraPose, CameraUniform};
+use crate::capture;
+use crate::culling::{reachable_sections, Aabb, CullingStats, Frustum, SectionPos};
 use crate::graphics::Graphics;
+use crate::instance::InstanceRaw;
+use crate::lights::{PointLight, PointLights};
 use crate::pipeline::Pipeline;
+use crate::post::PostProcess;
 use crate::quad;
 use crate::quad::Raw;
+use crate::resource_pack::{ResourcePack, SHADER_SOURCES};
+use crate::settings::Settings;
+use crate::shadow::Shadows;
+use crate::sky::Sky;
 use crate::texture::Texture;
+use crate::translucent::TranslucentQuads;
+use crate::watcher::FileWatcher;
+use crate::water::Water;
 use crate::Vertex;
 
-pub(crate) struct Renderer {
-    window: Window,
-    pub graphics: Graphics,
-    render_pipeline: Pipeline,
-    vertex_buffer: wgpu::Buffer,
-    num_vertices: u32,
-    index_buffer: wgpu::Buffer,
-    num_indices: u32,
-    diffuse_bind_group: wgpu::BindGroup,
-    diffuse_texture: Texture,
-    depth_texture: Texture,
-    pub camera: Camera,
-    camera_uniform: CameraUniform,
-    camera_buffer: wgpu::Buffer,
-    camera_bind_group: wgpu::BindGroup,
-    block_map: BlockMap,
-    instance_buffer: wgpu::Buffer,
-    pub(crate) mouse_pressed: bool,
+const HELD_TORCH: PointLight = PointLight {
+    position: [0.; 3],
+    radius: 14.,
+    color: [1., 0.75, 0.45],
+    intensity: 0.9,
+};
+
+const SCREENSHOT_DIRECTORY: &str = "screenshots";
+
+// With hot reloading the built-in shaders come from the source tree, so they can be edited too
+fn load_resource_pack(settings: &Settings) -> Result<ResourcePack, Box<dyn Error>> {
+    let mut pack = ResourcePack::load(&settings.resource_packs)?;
+    if settings.hot_reload {
+        pack.load_shader_sources(Path::new(SHADER_SOURCES))?;
+    }
+    Ok(pack)
 }
 
-impl Renderer {
-    // Creating some of the wgpu types requires async code
-    pub(crate) async fn new(window: Window) -> Self {
-        let graphics = Graphics::new(&window).await;
+/**
+ * Appends quads to the instance data with the flipped ones last, so each triangulation can be drawn
+ * as one range of instances. Returns the ranges of unflipped and flipped quads.
+ */
+fn push_instances<'a>(instance_data: &mut Vec<InstanceRaw>, quads: impl Iterator<Item = &'a Quad>) -> [Range<u32>; 2] {
+    let start = instance_data.len() as u32;
+    let mut quads: Vec<&Quad> = quads.collect();
+    quads.sort_by_key(|quad| quad.flipped);
+    let split = start + quads.iter().filter(|quad| !quad.flipped).count() as u32;
+
+    instance_data.extend(quads.into_iter().map(|quad| quad.to_raw()));
+    [start..split, split..instance_data.len() as u32]
+}
 
-        let grass_side_diffuse_texture = Texture::from_bytes(&graphics.device, &graphics.queue, include_bytes!("assets/grass_block_side.png"), "grass_block_side.png").unwrap();
-        let grass_top_diffuse_texture = Texture::from_bytes(&graphics.device, &graphics.queue, include_bytes!("assets/grass_block_top.png"), "grass_block_top.png").unwrap();
-        let dirt_diffuse_texture = Texture::from_bytes(&graphics.device, &graphics.queue, include_bytes!("assets/dirt.png"), "dirt.png").unwrap();
+/**
+ * Where a section's opaque or cutout quads are in the instance buffer, split into unflipped and
+ * flipped ones, and the box they fit in for culling.
+ */
+struct SectionInstances {
+    pos: SectionPos,
+    bounds: Aabb,
+    instances: [Range<u32>; 2],
+    // Whether culling left the section in for this frame
+    visible: bool,
+}
 
-        let depth_texture = Texture::create_depth_texture(&graphics.device, &graphics.config, "depth_texture");
+// Splits a chunk's quads up by the section of the block each is a face of, leaving out sections
+// without any
+fn by_section(chunk: Coord2DI, quads: Vec<&Quad>) -> Vec<(SectionPos, Vec<&Quad>)> {
+    let mut sections = vec![vec![]; SECTIONS];
+    for quad in quads {
+        sections[SectionPos::of_quad(quad).y as usize].push(quad);
+    }
+    sections.into_iter().enumerate()
+        .filter(|(_, quads)| !quads.is_empty())
+        .map(|(y, quads)| (SectionPos { chunk, y: y as i32 }, quads))
+        .collect()
+}
 
-        let camera = Camera::new(&graphics);
-        let (camera_uniform, camera_buffer, camera_bind_group, camera_bind_group_layout) = camera.bind(&graphics);
+fn owned(sections: Vec<(SectionPos, Vec<&Quad>)>) -> Vec<(SectionPos, Vec<Quad>)> {
+    sections.into_iter().map(|(pos, quads)| (pos, quads.into_iter().cloned().collect())).collect()
+}
 
-        let mut block_map: BlockMap = BlockMap::new();
+// Lays out the instances of each section's quads one after the other
+fn push_sections(instance_data: &mut Vec<InstanceRaw>, sections: Vec<(SectionPos, Vec<&Quad>)>) -> Vec<SectionInstances> {
+    sections.into_iter()
+        .filter_map(|(pos, quads)| {
+            let bounds = Aabb::around(quads.iter().copied())?;
+            let instances = push_instances(instance_data, quads.into_iter());
+            Some(SectionInstances { pos, bounds, instances, visible: true })
+        })
+        .collect()
+}
 
-        let chunk: Chunk = VanillaGenerator::new(Seed::random().get()).generate_chunk(Point2DI::new(0, 0));
+/**
+ * Everything built from the resource packs, which is rebuilt when they are reloaded: block
+ * textures, every pipeline and the meshes, whose texture layers may have moved.
+ */
+struct PackResources {
+    block_textures: BlockTextures,
+    render_pipeline: Pipeline,
+    cutout_pipeline: Pipeline,
+    translucent_pipeline: Pipeline,
+    opaque_sections: Vec<SectionInstances>,
+    cutout_sections: Vec<SectionInstances>,
+    instance_buffer: wgpu::Buffer,
+    // Opaque and cutout quads in the instance buffer, which all cast shadows
+    shadow_casters: [Range<u32>; 2],
+    translucent: TranslucentQuads,
+    water: Water,
+    sky: Sky,
+    shadows: Shadows,
+    post: PostProcess,
+}
 
-        for block in chunk.blocks {
-            block_map.push(block.to_vector([true, true, true, true, true, true]));
+impl PackResources {
+    fn new(
+        graphics: &Graphics,
+        world: &World,
+        settings: &Settings,
+        pack: &ResourcePack,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        depth_texture: &Texture,
+        point_lights: &PointLights,
+    ) -> Result<Self, Box<dyn Error>> {
+        // Invalid pack shaders are reported as an error rather than taking the game down
+        graphics.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let resources = Self::build(graphics, world, settings, pack, camera_bind_group_layout, depth_texture, point_lights);
+        if let Some(e) = pollster::block_on(graphics.device.pop_error_scope()) {
+            return Err(e.into());
         }
+        resources
+    }
 
-        let mut instance_data = vec![];
+    fn build(
+        graphics: &Graphics,
+        world: &World,
+        settings: &Settings,
+        pack: &ResourcePack,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        depth_texture: &Texture,
+        point_lights: &PointLights,
+    ) -> Result<Self, Box<dyn Error>> {
+        let block_textures = BlockTextures::new(graphics, pack, settings)?;
+        let texture_layers = pack.layers();
+
+        // The sky catches up with the renderer's clock on its next update
+        let sky = Sky::new(graphics, &world.clock, settings, camera_bind_group_layout, &pack.shader("sky.wgsl")?);
+        let shadows = Shadows::new(graphics, point_lights, &block_textures, &pack.shader("shadow.wgsl")?);
+        let post = PostProcess::new(graphics, settings, &pack.shader("post.wgsl")?);
+
+        let meshes: Vec<_> = world.chunks().map(|chunk| (chunk.pos, mesh_chunk(world, chunk.pos, &texture_layers))).collect();
 
-        for quad in block_map.quads() {
-            instance_data.push(quad.to_raw());
-        }
+        let mut instance_data = vec![];
+        let opaque_sections = push_sections(&mut instance_data, meshes.iter().flat_map(|(pos, mesh)| by_section(*pos, mesh.opaque.quads())).collect());
+        let num_opaque = instance_data.len() as u32;
+        let cutout_sections = push_sections(&mut instance_data, meshes.iter().flat_map(|(pos, mesh)| by_section(*pos, mesh.cutout.quads())).collect());
+        let shadow_casters = [0..num_opaque, num_opaque..instance_data.len() as u32];
+
+        let translucent = TranslucentQuads::new(graphics, meshes.iter()
+            .flat_map(|(pos, mesh)| owned(by_section(*pos, mesh.translucent.quads())))
+            .collect());
+        let water_quads = TranslucentQuads::new(graphics, meshes.iter()
+            .flat_map(|(pos, mesh)| owned(by_section(*pos, mesh.fluid.quads())))
+            .collect());
+        let water = Water::new(graphics, water_quads, depth_texture, camera_bind_group_layout, &sky.bind_group_layout, &pack.shader("water.wgsl")?);
 
         let instance_buffer = graphics.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Instance Buffer"),
@@ -73,62 +190,114 @@ impl Renderer {
             usage: wgpu::BufferUsages::VERTEX,
         });
 
-        let texture_bind_group_layout =
-            graphics.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        // This should match the filterable field of the
-                        // corresponding Texture entry above.
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: NonZeroU32::new(3),
-                    }
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
-
-        let diffuse_bind_group = graphics.device.create_bind_group(
-            &wgpu::BindGroupDescriptor {
-                layout: &texture_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::Sampler(&grass_side_diffuse_texture.sampler),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: TextureViewArray(vec![&grass_side_diffuse_texture.view, &grass_top_diffuse_texture.view, &dirt_diffuse_texture.view].as_ref()),
-                    }
-                ],
-                label: Some("diffuse_bind_group"),
-            }
-        );
-
         let render_pipeline_layout = graphics.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Render Pipeline Layout"),
-            bind_group_layouts: &[&texture_bind_group_layout, &camera_bind_group_layout],
+            bind_group_layouts: &[&block_textures.bind_group_layout, camera_bind_group_layout, &sky.bind_group_layout, &shadows.bind_group_layout],
             push_constant_ranges: &[],
         });
 
-        let render_pipeline = Pipeline::new(
-            &graphics,
-            "Main",
+        let shader = format!("{}\n{}", block_textures.shader_header, pack.shader("shader.wgsl")?);
+        let [render_pipeline, cutout_pipeline, translucent_pipeline] = [
+            ("Main", RenderLayer::Opaque),
+            ("Cutout", RenderLayer::Cutout),
+            ("Translucent", RenderLayer::Translucent),
+        ].map(|(label, layer)| Pipeline::new(
+            graphics,
+            label,
             "Main",
-            include_str!("shaders/shader.wgsl"),
+            &shader,
             Vertex::init_buffer_layout(),
             Some(&render_pipeline_layout),
-        );
+            layer,
+        ));
+
+        Ok(Self {
+            block_textures,
+            render_pipeline,
+            cutout_pipeline,
+            translucent_pipeline,
+            opaque_sections,
+            cutout_sections,
+            instance_buffer,
+            shadow_casters,
+            translucent,
+            water,
+            sky,
+            shadows,
+            post,
+        })
+    }
+}
+
+pub(crate) struct Renderer {
+    pub graphics: Graphics,
+    render_pipeline: Pipeline,
+    cutout_pipeline: Pipeline,
+    translucent_pipeline: Pipeline,
+    vertex_buffer: wgpu::Buffer,
+    num_vertices: u32,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+    // Instances of opaque and cutout quads by section
+    opaque_sections: Vec<SectionInstances>,
+    cutout_sections: Vec<SectionInstances>,
+    shadow_casters: [Range<u32>; 2],
+    culling_stats: CullingStats,
+    translucent: TranslucentQuads,
+    water: Water,
+    block_textures: BlockTextures,
+    depth_texture: Texture,
+    pub camera: Camera,
+    camera_uniform: CameraUniform,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    camera_bind_group_layout: wgpu::BindGroupLayout,
+    sky: Sky,
+    shadows: Shadows,
+    point_lights: PointLights,
+    post: PostProcess,
+    // Whether the camera carries a torch lighting its surroundings
+    held_light: bool,
+    // Whether to save the next frame as a screenshot
+    screenshot_requested: bool,
+    // Whether to reload the resource packs before the next frame
+    reload_requested: bool,
+    // The resource packs and shader sources, when they're reloaded as soon as they change
+    watcher: Option<FileWatcher>,
+    pub(crate) clock: WorldClock,
+    settings: Settings,
+    instance_buffer: wgpu::Buffer,
+    pub(crate) mouse_pressed: bool,
+}
+
+impl Renderer {
+    pub(crate) fn new(graphics: Graphics, world: &World, settings: Settings) -> Result<Self, Box<dyn Error>> {
+        let depth_texture = Texture::create_depth_texture(&graphics.device, &graphics.config, "depth_texture");
+
+        let mut camera = Camera::new(&graphics);
+        // Everything past the view distance is hidden by fog, with some room for chunk corners
+        camera.far = settings.view_distance() + CHUNK_SIZE as f32;
+        let (camera_uniform, camera_buffer, camera_bind_group, camera_bind_group_layout) = camera.bind(&graphics);
+
+        let clock = world.clock;
+        let point_lights = PointLights::new(&graphics);
+
+        let pack = load_resource_pack(&settings)?;
+        let PackResources {
+            block_textures,
+            render_pipeline,
+            cutout_pipeline,
+            translucent_pipeline,
+            opaque_sections,
+            cutout_sections,
+            instance_buffer,
+            shadow_casters,
+            translucent,
+            water,
+            sky,
+            shadows,
+            post,
+        } = PackResources::new(&graphics, world, &settings, &pack, &camera_bind_group_layout, &depth_texture, &point_lights)?;
 
         let vertex_buffer = graphics.device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
@@ -141,7 +310,7 @@ impl Renderer {
         let index_buffer = graphics.device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(quad::INDICES),
+                contents: bytemuck::cast_slice(&[quad::INDICES, quad::FLIPPED_INDICES].concat()),
                 usage: wgpu::BufferUsages::INDEX,
             }
         );
@@ -149,33 +318,182 @@ impl Renderer {
         let num_vertices = quad::VERTICES.len() as u32;
         let num_indices = quad::INDICES.len() as u32;
 
-        Self {
-            window,
+        let watcher = settings.hot_reload.then(|| {
+            let mut paths = settings.resource_packs.clone();
+            paths.push(SHADER_SOURCES.into());
+            FileWatcher::new(paths)
+        });
+
+        Ok(Self {
             graphics,
             render_pipeline,
+            cutout_pipeline,
+            translucent_pipeline,
             vertex_buffer,
             num_vertices,
             index_buffer,
             num_indices,
-            diffuse_bind_group,
-            diffuse_texture: grass_side_diffuse_texture,
+            opaque_sections,
+            cutout_sections,
+            shadow_casters,
+            culling_stats: CullingStats::default(),
+            translucent,
+            water,
+            block_textures,
             depth_texture,
             camera,
             camera_uniform,
             camera_buffer,
             camera_bind_group,
-            block_map,
+            camera_bind_group_layout,
+            sky,
+            shadows,
+            point_lights,
+            post,
+            held_light: false,
+            screenshot_requested: false,
+            reload_requested: false,
+            watcher,
+            clock,
+            settings,
             instance_buffer,
             mouse_pressed: false,
-        }
+        })
     }
 
-    pub(crate) fn window(&self) -> &Window {
-        &self.window
+    pub(crate) fn set_camera_pose(&mut self, pose: CameraPose) {
+        self.camera.set_pose(pose);
     }
 
     pub(crate) fn input(&mut self, event: &WindowEvent) -> bool {
         match event {
+            WindowEvent::KeyboardInput {
+                input:
+                KeyboardInput {
+                    virtual_keycode: Some(VirtualKeyCode::T),
+                    state: ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                // Skip ahead an hour
+                self.clock.set_time_of_day(self.clock.time_of_day() + DAY_LENGTH / 24);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                KeyboardInput {
+                    virtual_keycode: Some(VirtualKeyCode::F),
+                    state: ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.settings.fog = self.settings.fog.next();
+                log::info!("Fog: {:?}", self.settings.fog);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                KeyboardInput {
+                    virtual_keycode: Some(VirtualKeyCode::G),
+                    state: ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.settings.shadows = !self.settings.shadows;
+                log::info!("Shadows: {}", if self.settings.shadows { "on" } else { "off" });
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                KeyboardInput {
+                    virtual_keycode: Some(VirtualKeyCode::L),
+                    state: ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.held_light = !self.held_light;
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                KeyboardInput {
+                    virtual_keycode: Some(VirtualKeyCode::F2),
+                    state: ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.screenshot_requested = true;
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                KeyboardInput {
+                    virtual_keycode: Some(VirtualKeyCode::F5),
+                    state: ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.reload_requested = true;
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                KeyboardInput {
+                    virtual_keycode: Some(VirtualKeyCode::B),
+                    state: ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.settings.bloom = !self.settings.bloom;
+                log::info!("Bloom: {}", if self.settings.bloom { "on" } else { "off" });
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                KeyboardInput {
+                    virtual_keycode: Some(VirtualKeyCode::K),
+                    state: ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.settings.tonemapper = self.settings.tonemapper.next();
+                log::info!("Tonemapper: {:?}", self.settings.tonemapper);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                KeyboardInput {
+                    virtual_keycode: Some(VirtualKeyCode::X),
+                    state: ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.settings.fxaa = !self.settings.fxaa;
+                log::info!("FXAA: {}", if self.settings.fxaa { "on" } else { "off" });
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                KeyboardInput {
+                    virtual_keycode: Some(VirtualKeyCode::V),
+                    state: ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.settings.vignette = !self.settings.vignette;
+                log::info!("Vignette: {}", if self.settings.vignette { "on" } else { "off" });
+                true
+            }
             WindowEvent::KeyboardInput {
                 input:
                 KeyboardInput {
@@ -208,11 +526,34 @@ impl Renderer {
             self.graphics.config.height = new_size.height;
             self.camera.resize(&self.graphics);
             self.depth_texture = Texture::create_depth_texture(&self.graphics.device, &self.graphics.config, "depth_texture");
-            self.graphics.surface.configure(&self.graphics.device, &self.graphics.config);
+            self.water.resize(&self.graphics, &self.depth_texture);
+            self.post.resize(&self.graphics);
+            if let Some(surface) = &self.graphics.surface {
+                surface.configure(&self.graphics.device, &self.graphics.config);
+            }
         }
     }
 
-    pub(crate) fn update(&mut self, dt: Duration) {
+    pub(crate) fn update(&mut self, dt: Duration, world: &World) {
+        self.clock.advance(dt);
+
+        if self.watcher.as_mut().is_some_and(FileWatcher::changed) {
+            log::info!("Resource pack or shader files changed");
+            self.reload_requested = true;
+        }
+        if self.reload_requested {
+            self.reload_requested = false;
+            match self.reload(world) {
+                Ok(()) => log::info!("Reloaded resource packs"),
+                Err(e) => log::error!("Failed to reload resource packs, keeping the current ones: {}", e),
+            }
+        }
+
+        let eye = self.camera.eye;
+        let cell = Coord3DI::new(eye.x.round() as i32, eye.y.round() as i32, eye.z.round() as i32);
+        let underwater = world.get_block(cell).is_some_and(|block| block.material.is_fluid());
+        self.sky.update(&self.graphics, &self.clock, &self.settings, underwater);
+
         self.camera.update();
         self.camera_uniform.update_view_proj(&self.camera);
         self.graphics.queue.write_buffer(
@@ -220,30 +561,153 @@ impl Renderer {
             0,
             bytemuck::cast_slice(&[self.camera_uniform]),
         );
+        self.shadows.update(&self.graphics, &self.camera, &self.clock, self.settings.shadows);
+        self.translucent.sort(&self.graphics, self.camera.eye);
+        self.water.quads.sort(&self.graphics, self.camera.eye);
+        self.cull(world);
+        self.water.update(&self.graphics, &self.clock);
+        self.block_textures.update(&self.graphics, &self.clock);
+
+        let mut lights = vec![];
+        if self.held_light {
+            lights.push(HELD_TORCH.at(self.camera.eye.into()));
+        }
+        self.point_lights.update(&self.graphics, &lights);
+        self.post.update(&self.graphics, &self.settings);
+    }
+
+    /**
+     * How much culling left out of the last frame.
+     */
+    pub(crate) fn culling_stats(&self) -> CullingStats {
+        self.culling_stats
+    }
+
+    // Finds the sections in view of the camera as it is now, and not hidden from it behind solid
+    // rock, to only draw those
+    fn cull(&mut self, world: &World) {
+        let frustum = Frustum::from_matrix(&self.camera.global_matrix);
+        let reachable = self.settings.cave_culling.then(|| reachable_sections(world, self.camera.eye, &frustum));
+        let visible = |pos: SectionPos, bounds: &Aabb| frustum.intersects(bounds) && reachable.as_ref().is_none_or(|reachable| reachable.contains(&pos));
+
+        let mut stats = CullingStats::default();
+        for section in self.opaque_sections.iter_mut().chain(&mut self.cutout_sections) {
+            let quads = section.instances[1].end - section.instances[0].start;
+            section.visible = visible(section.pos, &section.bounds);
+            stats.meshes += 1;
+            stats.quads += quads;
+            if section.visible {
+                stats.visible_meshes += 1;
+                stats.visible_quads += quads;
+            }
+        }
+        stats += self.translucent.cull(visible);
+        stats += self.water.quads.cull(visible);
+        self.culling_stats = stats;
+    }
+
+    /**
+     * Loads the resource packs again and rebuilds everything made from them. Packs that fail to
+     * load or shaders that fail to compile leave the current ones in place.
+     */
+    fn reload(&mut self, world: &World) -> Result<(), Box<dyn Error>> {
+        let pack = load_resource_pack(&self.settings)?;
+        let PackResources {
+            block_textures,
+            render_pipeline,
+            cutout_pipeline,
+            translucent_pipeline,
+            opaque_sections,
+            cutout_sections,
+            instance_buffer,
+            shadow_casters,
+            translucent,
+            water,
+            sky,
+            shadows,
+            post,
+        } = PackResources::new(&self.graphics, world, &self.settings, &pack, &self.camera_bind_group_layout, &self.depth_texture, &self.point_lights)?;
+        self.block_textures = block_textures;
+        self.render_pipeline = render_pipeline;
+        self.cutout_pipeline = cutout_pipeline;
+        self.translucent_pipeline = translucent_pipeline;
+        self.opaque_sections = opaque_sections;
+        self.cutout_sections = cutout_sections;
+        self.instance_buffer = instance_buffer;
+        self.shadow_casters = shadow_casters;
+        self.translucent = translucent;
+        self.water = water;
+        self.sky = sky;
+        self.shadows = shadows;
+        self.post = post;
+        Ok(())
     }
 
     pub(crate) fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.graphics.surface.get_current_texture()?;
+        let surface = self.graphics.surface.as_ref().expect("Rendering to the screen needs a window");
+        let output = surface.get_current_texture()?;
 
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let commands = self.encode_frame(&view);
+
+        // submit will accept anything that implements IntoIter
+        self.graphics.queue.submit(std::iter::once(commands));
+        output.present();
+
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            match self.capture().and_then(|image| capture::save_screenshot(&image, Path::new(SCREENSHOT_DIRECTORY))) {
+                Ok(path) => log::info!("Saved screenshot to {}", path.display()),
+                Err(e) => log::error!("Failed to save screenshot: {}", e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Draws the current frame offscreen and reads it back.
+     */
+    pub(crate) fn capture(&self) -> Result<RgbaImage, Box<dyn Error>> {
+        let target = Texture::create_render_target(
+            &self.graphics.device,
+            self.graphics.config.width,
+            self.graphics.config.height,
+            self.graphics.config.format,
+            "capture_texture",
+        );
+        let commands = self.encode_frame(&target.view);
+        self.graphics.queue.submit(std::iter::once(commands));
+        capture::read_texture(&self.graphics, &target)
+    }
 
+    /**
+     * Records every pass of a frame, ending with the post-processing that writes into `view`.
+     */
+    fn encode_frame(&self, view: &wgpu::TextureView) -> wgpu::CommandBuffer {
         let mut encoder = self.graphics.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder"),
         });
 
+        // Translucent blocks let light through, so only opaque and cutout quads cast shadows. Sections
+        // out of view still cast them into it, so none are culled
+        self.shadows.render(
+            &mut encoder,
+            &self.block_textures.bind_group,
+            &self.vertex_buffer,
+            &self.instance_buffer,
+            &self.index_buffer,
+            &self.shadow_casters,
+        );
+
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view: self.post.scene_view(),
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
+                        load: wgpu::LoadOp::Clear(self.sky.clear_color()),
                         store: true,
                     },
                 })],
@@ -257,21 +721,66 @@ impl Renderer {
                 }),
             });
 
-            render_pass.set_pipeline(&self.render_pipeline.pipeline);
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+            self.sky.draw(&mut render_pass, &self.camera_bind_group);
+
+            render_pass.set_bind_group(0, &self.block_textures.bind_group, &[]);
 
             render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.sky.bind_group, &[]);
+            render_pass.set_bind_group(3, &self.shadows.bind_group, &[]);
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
             render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
 
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..self.block_map.quad_len() as _);
+            // Unflipped quads use the first triangulation in the index buffer, flipped ones the second
+            let indices = [0..self.num_indices, self.num_indices..self.num_indices * 2];
+            for (pipeline, sections) in [(&self.render_pipeline, &self.opaque_sections), (&self.cutout_pipeline, &self.cutout_sections)] {
+                render_pass.set_pipeline(&pipeline.pipeline);
+                for section in sections.iter().filter(|section| section.visible) {
+                    for (indices, instances) in indices.iter().zip(&section.instances).filter(|(_, instances)| !instances.is_empty()) {
+                        render_pass.draw_indexed(indices.clone(), 0, instances.clone());
+                    }
+                }
+            }
         }
 
-        // submit will accept anything that implements IntoIter
-        self.graphics.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Translucent Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: self.post.scene_view(),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                // Read-only, so the water can read how far the scene behind it is while drawing
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture.view,
+                    depth_ops: None,
+                    stencil_ops: None,
+                }),
+            });
 
-        Ok(())
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            // Water goes first, so glass in front of it blends over it. Glass under water is
+            // drawn over it too, which the two separate orders can't avoid
+            self.water.draw(&mut render_pass, &self.camera_bind_group, &self.sky.bind_group, self.num_indices);
+
+            // Drawn last, back to front, over everything they let through. Keeping the order
+            // matters more here than the triangulation, so flipped quads aren't split off
+            render_pass.set_pipeline(&self.translucent_pipeline.pipeline);
+            render_pass.set_bind_group(0, &self.block_textures.bind_group, &[]);
+            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.sky.bind_group, &[]);
+            render_pass.set_bind_group(3, &self.shadows.bind_group, &[]);
+            self.translucent.draw(&mut render_pass, self.num_indices);
+        }
+
+        self.post.render(&mut encoder, &self.settings, view);
+
+        encoder.finish()
     }
-}
\ No newline at end of file
+}
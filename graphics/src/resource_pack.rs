@@ -0,0 +1,541 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use image::{Rgba, RgbaImage};
+use image::imageops::FilterType;
+use serde_json::Value;
+
+use math::face::Face;
+use world::material::TextureLayers;
+
+use crate::preprocessor::preprocess;
+
+// The pack the game starts with, relative to the working directory. Packs given on top of it
+// only need the files they change
+pub const DEFAULT_RESOURCE_PACK: &str = "resources/default";
+// Block textures within a pack, as PNG files named after the texture
+const BLOCK_TEXTURES: &str = "textures/block/";
+// Suffix of the sidecar next to a texture that makes it an animation, e.g. `lava.png.mcmeta`
+const ANIMATION_METADATA: &str = ".mcmeta";
+// Block models within a pack, as JSON files named after the block id without its namespace
+const BLOCK_MODELS: &str = "models/block/";
+// Shaders within a pack, replacing the built-in ones of the same name
+const SHADERS: &str = "shaders/";
+// Name of the placeholder shown for textures a pack doesn't have
+const MISSING_TEXTURE: &str = "missing";
+
+// Where the built-in shaders are in the source tree, for editing them while the game runs
+pub const SHADER_SOURCES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders");
+
+/**
+ * Shaders the game is built with, used unless a pack replaces them.
+ */
+const BUILTIN_SHADERS: &[(&str, &str)] = &[
+    ("shader.wgsl", include_str!("shaders/shader.wgsl")),
+    ("camera.wgsl", include_str!("shaders/camera.wgsl")),
+    ("atmosphere.wgsl", include_str!("shaders/atmosphere.wgsl")),
+    ("lighting.wgsl", include_str!("shaders/lighting.wgsl")),
+    ("block_textures_array.wgsl", include_str!("shaders/block_textures_array.wgsl")),
+    ("block_textures_binding_array.wgsl", include_str!("shaders/block_textures_binding_array.wgsl")),
+    ("sky.wgsl", include_str!("shaders/sky.wgsl")),
+    ("shadow.wgsl", include_str!("shaders/shadow.wgsl")),
+    ("water.wgsl", include_str!("shaders/water.wgsl")),
+    ("post.wgsl", include_str!("shaders/post.wgsl")),
+];
+
+/**
+ * Model keys and the faces they set, applied in this order so the more specific ones win.
+ */
+const MODEL_FACES: &[(&str, &[Face])] = &[
+    ("all", &[Face::Up, Face::Down, Face::Left, Face::Right, Face::Front, Face::Back]),
+    ("side", &[Face::Left, Face::Right, Face::Front, Face::Back]),
+    ("up", &[Face::Up]),
+    ("down", &[Face::Down]),
+    ("left", &[Face::Left]),
+    ("right", &[Face::Right]),
+    ("front", &[Face::Front]),
+    ("back", &[Face::Back]),
+];
+
+/**
+ * A named block texture, decoded. Animated textures hold their first frame as the image.
+ */
+pub struct BlockTexture {
+    pub name: String,
+    pub image: RgbaImage,
+    pub animation: Option<Animation>,
+}
+
+impl BlockTexture {
+    /**
+     * The texture encoded as a PNG file.
+     */
+    pub fn to_png(&self) -> Result<Vec<u8>, image::ImageError> {
+        let mut bytes = std::io::Cursor::new(vec![]);
+        self.image.write_to(&mut bytes, image::ImageOutputFormat::Png)?;
+        Ok(bytes.into_inner())
+    }
+}
+
+/**
+ * The frames of an animated texture, stacked top to bottom in its PNG as squares, and the order
+ * they play in.
+ */
+#[derive(Clone)]
+pub struct Animation {
+    strip: Vec<RgbaImage>,
+    // Index into the strip and how many ticks each frame shows for
+    frames: Vec<(usize, u32)>,
+    // Whether to blend into the next frame rather than switching at once
+    interpolate: bool,
+}
+
+/**
+ * What an animation shows at some point in time: a frame and how far it has blended into the
+ * next, in 256ths.
+ */
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct AnimationFrame {
+    pub frame: usize,
+    pub next: usize,
+    pub blend: u8,
+}
+
+impl Animation {
+    /**
+     * Splits a strip into frames as described by its sidecar, e.g.
+     * `{"animation": {"frametime": 2, "interpolate": true, "frames": [0, 1, {"index": 2, "time": 8}]}}`.
+     * Without a frame list the strip plays top to bottom.
+     */
+    fn parse(image: &RgbaImage, metadata: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let metadata: Value = serde_json::from_slice(metadata)?;
+        let animation = metadata.get("animation").ok_or("Metadata has no animation")?;
+
+        let size = image.width();
+        if size == 0 || !image.height().is_multiple_of(size) {
+            return Err(format!("Strip of {}x{} isn't a stack of square frames", image.width(), image.height()).into());
+        }
+        let strip: Vec<RgbaImage> = (0..image.height() / size)
+            .map(|i| image::imageops::crop_imm(image, 0, i * size, size, size).to_image())
+            .collect();
+
+        let frame_time = match animation.get("frametime") {
+            Some(time) => time.as_u64().ok_or("Frame time must be a whole number of ticks")? as u32,
+            None => 1,
+        };
+        let interpolate = animation.get("interpolate").and_then(Value::as_bool).unwrap_or(false);
+        let frames = match animation.get("frames").and_then(Value::as_array) {
+            Some(frames) => frames.iter()
+                .map(|frame| match frame {
+                    Value::Object(frame) => Ok((
+                        frame.get("index").and_then(Value::as_u64).ok_or("Frame has no index")? as usize,
+                        frame.get("time").and_then(Value::as_u64).map_or(frame_time, |time| time as u32),
+                    )),
+                    frame => Ok((frame.as_u64().ok_or("Frame must be an index")? as usize, frame_time)),
+                })
+                .collect::<Result<Vec<_>, Box<dyn Error>>>()?,
+            None => (0..strip.len()).map(|i| (i, frame_time)).collect(),
+        };
+
+        if let Some((index, _)) = frames.iter().find(|(index, _)| *index >= strip.len()) {
+            return Err(format!("Frame {} is past the {} frames of the strip", index, strip.len()).into());
+        }
+        if frames.iter().all(|(_, time)| *time == 0) {
+            return Err("Animation has no frames that show for any time".into());
+        }
+        Ok(Self { strip, frames, interpolate })
+    }
+
+    /**
+     * The frame showing `ticks` into the animation, which loops.
+     */
+    pub fn frame_at(&self, ticks: f64) -> AnimationFrame {
+        let duration: u32 = self.frames.iter().map(|(_, time)| time).sum();
+        let mut tick = ticks.rem_euclid(duration as f64);
+        for (i, &(frame, time)) in self.frames.iter().enumerate() {
+            if tick < time as f64 {
+                let next = self.frames[(i + 1) % self.frames.len()].0;
+                let blend = if self.interpolate { (tick / time as f64 * 256.0) as u8 } else { 0 };
+                return AnimationFrame { frame, next, blend };
+            }
+            tick -= time as f64;
+        }
+        // Only reached through rounding at the very end of the loop
+        let frame = self.frames[0].0;
+        AnimationFrame { frame, next: frame, blend: 0 }
+    }
+
+    /**
+     * The image of a frame, blended into the next one as far as it says.
+     */
+    pub fn image(&self, frame: AnimationFrame) -> RgbaImage {
+        let from = &self.strip[frame.frame];
+        if frame.blend == 0 {
+            return from.clone();
+        }
+        let to = &self.strip[frame.next];
+        let blend = frame.blend as u32;
+        RgbaImage::from_fn(from.width(), from.height(), |x, y| {
+            let (a, b) = (from.get_pixel(x, y).0, to.get_pixel(x, y).0);
+            Rgba(std::array::from_fn(|c| ((a[c] as u32 * (256 - blend) + b[c] as u32 * blend) / 256) as u8))
+        })
+    }
+}
+
+/**
+ * Files of a pack by their path within it, with `/` separators.
+ */
+type PackFiles = BTreeMap<String, Vec<u8>>;
+
+fn read_directory(root: &Path, dir: &Path, files: &mut PackFiles) -> Result<(), Box<dyn Error>> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            read_directory(root, &path, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let name = relative.components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.insert(name, std::fs::read(&path)?);
+        }
+    }
+    Ok(())
+}
+
+fn read_zip(path: &Path, files: &mut PackFiles) -> Result<(), Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let mut bytes = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut bytes)?;
+        files.insert(entry.name().trim_start_matches('/').to_string(), bytes);
+    }
+    Ok(())
+}
+
+fn read_pack(path: &Path) -> Result<PackFiles, Box<dyn Error>> {
+    let mut files = PackFiles::new();
+    if path.is_dir() {
+        read_directory(path, path, &mut files)?;
+    } else {
+        read_zip(path, &mut files)?;
+    }
+    Ok(files)
+}
+
+/**
+ * Resource packs stacked on top of each other: block textures, block models and shaders, with
+ * each pack's files replacing those of the same name in the packs before it. Textures are all
+ * brought to the same size so they can be layers of one texture array, and the first texture is
+ * always the missing texture placeholder, in line with `TextureLayers::MISSING`.
+ */
+pub struct ResourcePack {
+    textures: Vec<BlockTexture>,
+    // Per face texture names by block id, from the models
+    models: BTreeMap<String, [Option<String>; 6]>,
+    shaders: BTreeMap<String, String>,
+    // Built-in shaders read from disk rather than the ones built in
+    shader_sources: BTreeMap<String, String>,
+}
+
+impl ResourcePack {
+    /**
+     * Loads packs in order of priority, lowest first. Each one is either a directory or a zip
+     * file. Files that fail to decode are skipped with a warning.
+     */
+    pub fn load(packs: &[impl AsRef<Path>]) -> Result<Self, Box<dyn Error>> {
+        let mut files = PackFiles::new();
+        for pack in packs {
+            let pack = pack.as_ref();
+            let pack_files = read_pack(pack).map_err(|e| format!("Failed to read resource pack {}: {}", pack.display(), e))?;
+            log::info!("Loaded resource pack {} with {} files", pack.display(), pack_files.len());
+            files.extend(pack_files);
+        }
+
+        // Ordered by name, so the layers and with them the meshes come out the same on every run
+        let mut textures = vec![];
+        let mut models = BTreeMap::new();
+        let mut shaders = BTreeMap::new();
+        for (path, bytes) in &files {
+            if let Some(name) = path.strip_prefix(BLOCK_TEXTURES).and_then(|name| name.strip_suffix(".png")) {
+                match image::load_from_memory(bytes) {
+                    Ok(image) => textures.push(block_texture(name, image.to_rgba8(), files.get(&format!("{}{}", path, ANIMATION_METADATA)))),
+                    Err(e) => log::warn!("Skipping texture {}: {}", path, e),
+                }
+            } else if let Some(block) = path.strip_prefix(BLOCK_MODELS).and_then(|name| name.strip_suffix(".json")) {
+                match parse_model(bytes) {
+                    Ok(faces) => { models.insert(block.to_string(), faces); }
+                    Err(e) => log::warn!("Skipping model {}: {}", path, e),
+                }
+            } else if let Some(name) = path.strip_prefix(SHADERS) {
+                match String::from_utf8(bytes.clone()) {
+                    Ok(source) => { shaders.insert(name.to_string(), source); }
+                    Err(e) => log::warn!("Skipping shader {}: {}", path, e),
+                }
+            }
+        }
+        log::info!("Resource packs hold {} block textures, {} block models and {} shaders", textures.len(), models.len(), shaders.len());
+
+        Ok(Self {
+            textures: with_missing_texture(textures),
+            models,
+            shaders,
+            shader_sources: BTreeMap::new(),
+        })
+    }
+
+    /**
+     * Reads the built-in shaders from a directory instead, such as `SHADER_SOURCES`, so they can
+     * be changed without rebuilding the game. Packs still replace them.
+     */
+    pub fn load_shader_sources(&mut self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        for entry in std::fs::read_dir(dir).map_err(|e| format!("Failed to read shaders from {}: {}", dir.display(), e))? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "wgsl") {
+                let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                self.shader_sources.insert(name, std::fs::read_to_string(&path)?);
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * All textures in layer order, starting with the missing texture.
+     */
+    pub fn textures(&self) -> &[BlockTexture] {
+        &self.textures
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BlockTexture> {
+        self.textures.iter().find(|texture| texture.name == name)
+    }
+
+    /**
+     * The layer of each texture and the textures models put on each face, for meshing.
+     */
+    pub fn layers(&self) -> TextureLayers {
+        let layers = TextureLayers::new(self.textures.iter().map(|texture| texture.name.as_str()));
+        self.models.iter().fold(layers, |layers, (block, faces)| layers.with_model(block, faces.clone()))
+    }
+
+    /**
+     * The source of a shader with its includes and defines expanded. Each file comes from the
+     * highest pack that has one or else from the built-in shaders.
+     */
+    pub fn shader(&self, name: &str) -> Result<String, Box<dyn Error>> {
+        preprocess(name, |name| {
+            self.shaders.get(name).or_else(|| self.shader_sources.get(name)).map(String::as_str)
+                .or_else(|| BUILTIN_SHADERS.iter().find(|(builtin, _)| *builtin == name).map(|(_, source)| *source))
+        })
+    }
+}
+
+/**
+ * Reads the face textures of a block model, e.g. `{"textures": {"all": "dirt", "up": "grass_block_top"}}`.
+ */
+fn parse_model(bytes: &[u8]) -> Result<[Option<String>; 6], Box<dyn Error>> {
+    let model: Value = serde_json::from_slice(bytes)?;
+    let textures = model.get("textures").and_then(Value::as_object).ok_or("Model has no textures")?;
+
+    let mut faces: [Option<String>; 6] = Default::default();
+    for (key, covered) in MODEL_FACES {
+        if let Some(texture) = textures.get(*key) {
+            let texture = texture.as_str().ok_or_else(|| format!("Texture of {} must be a name", key))?;
+            for face in covered.iter() {
+                faces[*face as usize] = Some(texture.to_string());
+            }
+        }
+    }
+    if let Some(key) = textures.keys().find(|key| !MODEL_FACES.iter().any(|(face, _)| face == key)) {
+        log::warn!("Ignoring unknown face {} in model", key);
+    }
+    Ok(faces)
+}
+
+// An animated texture keeps its first frame as the image; one with a broken sidecar stays whole
+fn block_texture(name: &str, image: RgbaImage, metadata: Option<&Vec<u8>>) -> BlockTexture {
+    let animation = metadata.and_then(|metadata| match Animation::parse(&image, metadata) {
+        Ok(animation) => Some(animation),
+        Err(e) => {
+            log::warn!("Not animating texture {}: {}", name, e);
+            None
+        }
+    });
+    let image = match &animation {
+        Some(animation) => animation.image(animation.frame_at(0.0)),
+        None => image,
+    };
+    BlockTexture { name: name.to_string(), image, animation }
+}
+
+// Textures are scaled up to the largest one, keeping pixel art crisp
+fn with_missing_texture(mut textures: Vec<BlockTexture>) -> Vec<BlockTexture> {
+    let size = textures.iter().map(|texture| texture.image.width().max(texture.image.height())).max().unwrap_or(16);
+    for texture in textures.iter_mut().filter(|texture| texture.image.dimensions() != (size, size)) {
+        log::warn!("Resizing texture {} from {}x{} to {}x{}", texture.name, texture.image.width(), texture.image.height(), size, size);
+        texture.image = image::imageops::resize(&texture.image, size, size, FilterType::Nearest);
+        if let Some(animation) = &mut texture.animation {
+            for frame in &mut animation.strip {
+                *frame = image::imageops::resize(frame, size, size, FilterType::Nearest);
+            }
+        }
+    }
+
+    textures.insert(0, BlockTexture { name: MISSING_TEXTURE.to_string(), image: missing_texture(size), animation: None });
+    textures
+}
+
+// Magenta and black squares, hard to mistake for a real texture
+fn missing_texture(size: u32) -> RgbaImage {
+    let half = (size / 2).max(1);
+    RgbaImage::from_fn(size, size, |x, y| {
+        if (x / half + y / half).is_multiple_of(2) {
+            Rgba([255, 0, 255, 255])
+        } else {
+            Rgba([0, 0, 0, 255])
+        }
+    })
+}
+
+/**
+ * Averages the opaque pixels of a texture, used wherever a block is drawn as a single colour.
+ */
+pub fn average_color(image: &RgbaImage) -> [u8; 3] {
+    let mut sum = [0u64; 3];
+    let mut count = 0u64;
+
+    for pixel in image.pixels().filter(|pixel| pixel.0[3] > 0) {
+        for (total, value) in sum.iter_mut().zip(pixel.0) {
+            *total += value as u64;
+        }
+        count += 1;
+    }
+
+    let count = count.max(1);
+    [(sum[0] / count) as u8, (sum[1] / count) as u8, (sum[2] / count) as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use world::material::GRASS;
+
+    use super::*;
+
+    fn write_pack(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("resource-pack-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, bytes) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, bytes).unwrap();
+        }
+        dir
+    }
+
+    fn png(color: [u8; 4]) -> Vec<u8> {
+        let texture = BlockTexture { name: String::new(), image: RgbaImage::from_pixel(2, 2, Rgba(color)), animation: None };
+        texture.to_png().unwrap()
+    }
+
+    #[test]
+    fn later_packs_override_earlier_ones() {
+        let (red, gray, blue) = (png([255, 0, 0, 255]), png([128, 128, 128, 255]), png([0, 0, 255, 255]));
+        let base = write_pack("base", &[
+            ("textures/block/dirt.png", &red),
+            ("textures/block/stone.png", &gray),
+            ("models/block/grass_block.json", br#"{"textures": {"all": "dirt"}}"#),
+            ("shaders/sky.wgsl", b"// base sky"),
+        ]);
+        let top = write_pack("top", &[
+            ("textures/block/dirt.png", &blue),
+            ("models/block/grass_block.json", br#"{"textures": {"all": "stone"}}"#),
+            ("shaders/sky.wgsl", b"// top sky"),
+        ]);
+        let pack = ResourcePack::load(&[&base, &top]).unwrap();
+
+        let names: Vec<_> = pack.textures().iter().map(|texture| texture.name.as_str()).collect();
+        assert_eq!(names, [MISSING_TEXTURE, "dirt", "stone"]);
+        assert_eq!(pack.get("dirt").unwrap().image.get_pixel(0, 0), &Rgba([0, 0, 255, 255]));
+        assert_eq!(pack.get("stone").unwrap().image.get_pixel(0, 0), &Rgba([128, 128, 128, 255]));
+
+        let layers = pack.layers();
+        assert_eq!(layers.face_layer(&GRASS, Face::Up), layers.layer("stone"));
+        assert_eq!(pack.shader("sky.wgsl").unwrap().trim(), "// top sky");
+        // Shaders no pack has stay the built-in ones
+        assert!(pack.shader("shadow.wgsl").unwrap().contains("fn vs_main"));
+
+        std::fs::remove_dir_all(base).unwrap();
+        std::fs::remove_dir_all(top).unwrap();
+    }
+
+    #[test]
+    fn specific_model_faces_win_over_side_and_all() {
+        let faces = parse_model(br#"{"textures": {"front": "front", "side": "side", "all": "all", "up": "top"}}"#).unwrap();
+        let face = |face: Face| faces[face as usize].as_deref();
+        assert_eq!(face(Face::Up), Some("top"));
+        assert_eq!(face(Face::Down), Some("all"));
+        assert_eq!(face(Face::Front), Some("front"));
+        for side in [Face::Left, Face::Right, Face::Back] {
+            assert_eq!(face(side), Some("side"));
+        }
+    }
+
+    // A strip of one pixel frames, each a shade of grey ten times its index
+    fn strip(frames: u32) -> RgbaImage {
+        RgbaImage::from_fn(1, frames, |_, y| Rgba([y as u8 * 10, y as u8 * 10, y as u8 * 10, 255]))
+    }
+
+    fn animation(frames: u32, metadata: &str) -> Result<Animation, Box<dyn Error>> {
+        Animation::parse(&strip(frames), format!(r#"{{"animation": {}}}"#, metadata).as_bytes())
+    }
+
+    #[test]
+    fn plays_the_strip_or_the_frame_list() {
+        assert_eq!(animation(3, "{}").unwrap().frames, [(0, 1), (1, 1), (2, 1)]);
+        assert_eq!(animation(3, r#"{"frametime": 3, "frames": [2, 0, 2]}"#).unwrap().frames, [(2, 3), (0, 3), (2, 3)]);
+    }
+
+    #[test]
+    fn frames_can_override_the_frame_time() {
+        let animation = animation(2, r#"{"frametime": 2, "frames": [0, {"index": 1, "time": 5}]}"#).unwrap();
+        assert_eq!(animation.frames, [(0, 2), (1, 5)]);
+    }
+
+    #[test]
+    fn loops_around_the_frames() {
+        let animation = animation(2, r#"{"frametime": 2, "frames": [0, {"index": 1, "time": 5}]}"#).unwrap();
+        let frame = |ticks| animation.frame_at(ticks).frame;
+        assert_eq!([frame(0.), frame(1.9), frame(2.), frame(6.9)], [0, 0, 1, 1]);
+        assert_eq!([frame(7.), frame(9.), frame(70.5)], [0, 1, 0]);
+        assert_eq!(frame(-1.), 1);
+        assert_eq!(animation.frame_at(3.), AnimationFrame { frame: 1, next: 0, blend: 0 });
+    }
+
+    #[test]
+    fn interpolates_into_the_next_frame() {
+        let animation = animation(3, r#"{"frametime": 4, "interpolate": true}"#).unwrap();
+        let frame = animation.frame_at(10.);
+        assert_eq!(frame, AnimationFrame { frame: 2, next: 0, blend: 128 });
+        assert_eq!(animation.image(frame).get_pixel(0, 0), &Rgba([10, 10, 10, 255]));
+        assert_eq!(animation.image(animation.frame_at(4.)).get_pixel(0, 0), &Rgba([10, 10, 10, 255]));
+    }
+
+    #[test]
+    fn rejects_broken_animations() {
+        let metadata = br#"{"animation": {}}"#;
+        assert!(Animation::parse(&RgbaImage::new(2, 3), metadata).is_err());
+        assert!(Animation::parse(&RgbaImage::new(0, 0), metadata).is_err());
+        assert!(animation(2, r#"{"frames": [0, 2]}"#).is_err());
+        assert!(animation(2, r#"{"frametime": 0}"#).is_err());
+        assert!(animation(2, r#"{"frames": [{"index": 0, "time": 0}, {"index": 1, "time": 0}]}"#).is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use std::path::PathBuf;
+
+use math::CHUNK_SIZE;
+
+use crate::resource_pack::DEFAULT_RESOURCE_PACK;
+
+// Fog starts at this share of the view distance and is complete at the view distance
+const FOG_START: f32 = 0.6;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FogMode {
+    Off,
+    // Fades in evenly between the fog start and end
+    Linear,
+    // Thickens quickly past the fog start, leaving a softer edge near the end
+    Exponential,
+}
+
+impl FogMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "off" => Some(FogMode::Off),
+            "linear" => Some(FogMode::Linear),
+            "exponential" | "exp" => Some(FogMode::Exponential),
+            _ => None,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            FogMode::Off => FogMode::Linear,
+            FogMode::Linear => FogMode::Exponential,
+            FogMode::Exponential => FogMode::Off,
+        }
+    }
+}
+
+/**
+ * Curve mapping the unbounded brightness of the scene onto what the display can show.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tonemapper {
+    // Clips everything brighter than white
+    Off,
+    // The filmic curve fitted to the Academy Color Encoding System
+    Aces,
+    // Keeps bright colours from skewing in hue as they wash out towards white
+    Agx,
+}
+
+impl Tonemapper {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "off" | "none" => Some(Tonemapper::Off),
+            "aces" => Some(Tonemapper::Aces),
+            "agx" => Some(Tonemapper::Agx),
+            _ => None,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Tonemapper::Off => Tonemapper::Aces,
+            Tonemapper::Aces => Tonemapper::Agx,
+            Tonemapper::Agx => Tonemapper::Off,
+        }
+    }
+}
+
+/**
+ * Rendering options chosen by the player.
+ */
+#[derive(Clone, Debug)]
+pub struct Settings {
+    // How many chunks around the camera are visible
+    pub render_distance: u32,
+    pub fog: FogMode,
+    // Whether the sun and moon cast shadows
+    pub shadows: bool,
+    // Post-processing steps, each of which can be left out on slower hardware
+    pub bloom: bool,
+    pub tonemapper: Tonemapper,
+    pub gamma: bool,
+    pub fxaa: bool,
+    pub vignette: bool,
+    // Resource packs as directories or zip files, each overriding the ones before it
+    pub resource_packs: Vec<PathBuf>,
+    // Most texels block textures are sampled with along surfaces seen at a glancing angle, a
+    // power of two up to 16 where 1 turns anisotropic filtering off
+    pub anisotropy: u8,
+    // Reads the built-in shaders from the source tree, and reloads them and the resource packs
+    // whenever their files change
+    pub hot_reload: bool,
+    // Leaves out caves and other spaces the camera can't see into through solid rock
+    pub cave_culling: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            render_distance: 8,
+            fog: FogMode::Linear,
+            shadows: true,
+            bloom: true,
+            tonemapper: Tonemapper::Aces,
+            gamma: true,
+            fxaa: true,
+            vignette: false,
+            resource_packs: vec![PathBuf::from(DEFAULT_RESOURCE_PACK)],
+            anisotropy: 8,
+            hot_reload: false,
+            cave_culling: true,
+        }
+    }
+}
+
+impl Settings {
+    /**
+     * Reads an anisotropy setting, which the GPU only takes as a power of two up to 16.
+     */
+    pub fn parse_anisotropy(value: &str) -> Option<u8> {
+        value.parse().ok().filter(|anisotropy: &u8| anisotropy.is_power_of_two() && *anisotropy <= 16)
+    }
+
+    /**
+     * How far the camera can see, in blocks.
+     */
+    pub fn view_distance(&self) -> f32 {
+        (self.render_distance as usize * CHUNK_SIZE) as f32
+    }
+
+    pub fn fog_range(&self) -> (f32, f32) {
+        let view_distance = self.view_distance();
+        (view_distance * FOG_START, view_distance)
+    }
+}
@@ -0,0 +1,72 @@
+// The sky, bound at SKY_GROUP, which the including shader defines, and the colour of the air
+// looking in any direction. Blocks fog into it and water reflects it, so both match the sky
+
+struct SkyUniform {
+    sun: vec4<f32>,
+    moon: vec4<f32>,
+    zenith: vec4<f32>,
+    horizon: vec4<f32>,
+    light_color: vec4<f32>,
+    params: vec4<f32>,
+    fog: vec4<f32>,
+    underwater: vec4<f32>
+};
+@group(SKY_GROUP) @binding(0)
+var<uniform> sky: SkyUniform;
+
+const PI: f32 = 3.14159265;
+const SUNSET_COLOR: vec3<f32> = vec3<f32>(0.9, 0.3, 0.06);
+
+// Light scattered by air molecules, brightest looking towards or away from the sun
+fn rayleigh_phase(cos_angle: f32) -> f32 {
+    return 3.0 / (16.0 * PI) * (1.0 + cos_angle * cos_angle);
+}
+
+// Light scattered forwards by haze, giving the bright halo around the sun
+fn mie_phase(cos_angle: f32) -> f32 {
+    let g = 0.76;
+    let g2 = g * g;
+    return (1.0 - g2) / (4.0 * PI * pow(1.0 + g2 - 2.0 * g * cos_angle, 1.5));
+}
+
+fn atmosphere(direction: vec3<f32>) -> vec3<f32> {
+    let height = max(direction.y, 0.0);
+    let cos_sun = dot(direction, sky.sun.xyz);
+
+    // Looking towards the horizon passes through more air, washing the colour out
+    let optical_depth = exp(-height * 4.0);
+    var color = mix(sky.zenith.rgb, sky.horizon.rgb, optical_depth);
+    color *= 0.75 + rayleigh_phase(cos_sun) * PI;
+
+    // Sunrise and sunset glow, strongest low down on the side of the sun
+    let glow = sky.horizon.w * pow(max(cos_sun, 0.0), 4.0) * optical_depth;
+    color = mix(color, SUNSET_COLOR, glow);
+
+    // Haze around the sun, as long as it is up
+    let sun_up = smoothstep(-0.15, 0.05, sky.sun.y);
+    color += sky.light_color.rgb * mie_phase(cos_sun) * 0.05 * sun_up;
+
+    // Below the horizon the sky fades into a darker haze
+    if direction.y < 0.0 {
+        color *= mix(1.0, 0.5, min(-direction.y * 4.0, 1.0));
+    }
+    return color;
+}
+
+// How much of the fog colour covers a surface `distance` blocks from the camera
+fn fog_amount(distance: f32) -> f32 {
+    if sky.underwater.w > 0.0 {
+        return 1.0 - exp(-distance * sky.underwater.w);
+    }
+
+    let start = sky.fog.x;
+    let end = sky.fog.y;
+    let mode = u32(sky.fog.w);
+    if mode == 1u {
+        return clamp((distance - start) / (end - start), 0.0, 1.0);
+    }
+    if mode == 2u {
+        return 1.0 - exp(-sky.fog.z * max(distance - start, 0.0));
+    }
+    return 0.0;
+}
@@ -0,0 +1,10 @@
+// Block textures as the layers of one array texture, for adapters without binding arrays
+
+@group(0) @binding(0)
+var s_diffuse: sampler;
+@group(0) @binding(1)
+var t_diffuse: texture_2d_array<f32>;
+
+fn sample_block(index: u32, tex_coords: vec2<f32>) -> vec4<f32> {
+    return textureSample(t_diffuse, s_diffuse, tex_coords, i32(index));
+}
@@ -0,0 +1,10 @@
+// Block textures bound one by one, picked per fragment
+
+@group(0) @binding(0)
+var s_diffuse: sampler;
+@group(0) @binding(1)
+var t_diffuse: binding_array<texture_2d<f32>>;
+
+fn sample_block(index: u32, tex_coords: vec2<f32>) -> vec4<f32> {
+    return textureSample(t_diffuse[index], s_diffuse, tex_coords);
+}
@@ -0,0 +1,9 @@
+// The camera, bound at CAMERA_GROUP, which the including shader defines
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    eye: vec4<f32>
+};
+@group(CAMERA_GROUP) @binding(0)
+var<uniform> camera: CameraUniform;
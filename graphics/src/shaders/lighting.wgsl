@@ -0,0 +1,6 @@
+// Block and sky light levels, from 0 to 15, as they are lit
+
+// Each light level is 80% as bright as the one above it
+fn light_brightness(level: f32) -> f32 {
+    return pow(0.8, 15.0 - level);
+}
@@ -0,0 +1,192 @@
+// Fullscreen post-processing steps, each reading the output of the step before it
+
+struct PostUniform {
+    // Exposure, tonemapper (0 off, 1 ACES, 2 AgX) and whether to apply gamma
+    tone: vec4<f32>,
+    // Bloom threshold and strength, then vignette strength
+    effects: vec4<f32>
+};
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+// The blurred bloom, only read when compositing
+@group(0) @binding(1)
+var t_bloom: texture_2d<f32>;
+@group(0) @binding(2)
+var s_linear: sampler;
+@group(0) @binding(3)
+var<uniform> post: PostUniform;
+
+// Offsets and weights of a 9 tap gaussian folded into 5 linearly filtered samples
+const BLUR_OFFSET_1: f32 = 1.3846153846;
+const BLUR_OFFSET_2: f32 = 3.2307692308;
+const BLUR_WEIGHT_0: f32 = 0.2270270270;
+const BLUR_WEIGHT_1: f32 = 0.3162162162;
+const BLUR_WEIGHT_2: f32 = 0.0702702703;
+
+const FXAA_SPAN_MAX: f32 = 8.0;
+const FXAA_REDUCE_MUL: f32 = 0.125;
+const FXAA_REDUCE_MIN: f32 = 0.0078125;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One triangle large enough to cover the whole screen
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let screen = corner * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(screen, 0.0, 1.0);
+    out.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    return out;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Keeps only what is brighter than the threshold, at half resolution
+@fragment
+fn fs_bright(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_source, s_linear, in.uv).rgb;
+    let brightness = luminance(color);
+    let excess = max(brightness - post.effects.x, 0.0) / max(brightness, 0.0001);
+    return vec4<f32>(color * excess, 1.0);
+}
+
+fn blur(uv: vec2<f32>, direction: vec2<f32>) -> vec4<f32> {
+    let step = direction / vec2<f32>(textureDimensions(t_source));
+    var color = textureSample(t_source, s_linear, uv).rgb * BLUR_WEIGHT_0;
+    color += textureSample(t_source, s_linear, uv + step * BLUR_OFFSET_1).rgb * BLUR_WEIGHT_1;
+    color += textureSample(t_source, s_linear, uv - step * BLUR_OFFSET_1).rgb * BLUR_WEIGHT_1;
+    color += textureSample(t_source, s_linear, uv + step * BLUR_OFFSET_2).rgb * BLUR_WEIGHT_2;
+    color += textureSample(t_source, s_linear, uv - step * BLUR_OFFSET_2).rgb * BLUR_WEIGHT_2;
+    return vec4<f32>(color, 1.0);
+}
+
+@fragment
+fn fs_blur_horizontal(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2<f32>(1.0, 0.0));
+}
+
+@fragment
+fn fs_blur_vertical(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2<f32>(0.0, 1.0));
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    return clamp((color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Polynomial fit of the AgX default contrast curve
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let outset = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var x = inset * max(color, vec3<f32>(0.0000001));
+    x = (clamp(log2(x), vec3<f32>(min_ev), vec3<f32>(max_ev)) - min_ev) / (max_ev - min_ev);
+    x = outset * agx_contrast(x);
+    // The curve gives display values, brought back to linear for the gamma step
+    return pow(clamp(x, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(2.2));
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+// Adds the bloom and brings the scene into the range of the display
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = textureSample(t_source, s_linear, in.uv).rgb;
+    color += textureSample(t_bloom, s_linear, in.uv).rgb * post.effects.y;
+    color *= post.tone.x;
+
+    let tonemapper = u32(post.tone.y);
+    if tonemapper == 1u {
+        color = aces(color);
+    } else if tonemapper == 2u {
+        color = agx(color);
+    } else {
+        color = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+    }
+
+    if post.tone.z > 0.0 {
+        color = linear_to_srgb(color);
+    }
+    return vec4<f32>(color, 1.0);
+}
+
+// Timothy Lottes' fast approximate anti-aliasing, blurring along edges found from the luma
+@fragment
+fn fs_fxaa(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    let rgb_nw = textureSample(t_source, s_linear, in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb;
+    let rgb_ne = textureSample(t_source, s_linear, in.uv + vec2<f32>(1.0, -1.0) * texel).rgb;
+    let rgb_sw = textureSample(t_source, s_linear, in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb;
+    let rgb_se = textureSample(t_source, s_linear, in.uv + vec2<f32>(1.0, 1.0) * texel).rgb;
+    let rgb_m = textureSample(t_source, s_linear, in.uv).rgb;
+
+    let luma = vec3<f32>(0.299, 0.587, 0.114);
+    let luma_nw = dot(rgb_nw, luma);
+    let luma_ne = dot(rgb_ne, luma);
+    let luma_sw = dot(rgb_sw, luma);
+    let luma_se = dot(rgb_se, luma);
+    let luma_m = dot(rgb_m, luma);
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    var direction = vec2<f32>(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL, FXAA_REDUCE_MIN);
+    let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, vec2<f32>(-FXAA_SPAN_MAX), vec2<f32>(FXAA_SPAN_MAX)) * texel;
+
+    let rgb_a = 0.5 * (
+        textureSample(t_source, s_linear, in.uv + direction * (1.0 / 3.0 - 0.5)).rgb +
+        textureSample(t_source, s_linear, in.uv + direction * (2.0 / 3.0 - 0.5)).rgb
+    );
+    let rgb_b = rgb_a * 0.5 + 0.25 * (
+        textureSample(t_source, s_linear, in.uv + direction * -0.5).rgb +
+        textureSample(t_source, s_linear, in.uv + direction * 0.5).rgb
+    );
+
+    let luma_b = dot(rgb_b, luma);
+    if luma_b < luma_min || luma_b > luma_max {
+        return vec4<f32>(rgb_a, 1.0);
+    }
+    return vec4<f32>(rgb_b, 1.0);
+}
+
+// Darkens the corners of the screen
+@fragment
+fn fs_vignette(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_source, s_linear, in.uv).rgb;
+    let distance = length(in.uv - 0.5) * 1.41421356;
+    let darken = smoothstep(0.4, 1.1, distance) * post.effects.z;
+    return vec4<f32>(color * (1.0 - darken), 1.0);
+}
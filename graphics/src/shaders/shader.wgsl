@@ -1,12 +1,13 @@
 // Vertex shader
 
-struct CameraUniform {
-    view_proj: mat4x4<f32>
-};
-@group(1) @binding(0) // 1.
-var<uniform> camera: CameraUniform;
+#define CAMERA_GROUP 1
+#define SKY_GROUP 2
+#include "camera.wgsl"
+#include "atmosphere.wgsl"
+#include "lighting.wgsl"
 
 struct VertexInput {
+    @builtin(vertex_index) index: u32,
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>
 }
@@ -16,13 +17,25 @@ struct InstanceInput {
     @location(4) instance_matrix_2: vec4<f32>,
     @location(5) instance_matrix_3: vec4<f32>,
     @location(6) instance_matrix_4: vec4<f32>,
-    @location(7) texture_index: u32
+    @location(7) texture_index: u32,
+    @location(8) light: u32,
+    @location(9) ao: u32,
+    @location(11) emission: u32
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
-    @location(1) texture_index: u32
+    @location(1) texture_index: u32,
+    // Sky and block light level, interpolated between the corners
+    @location(2) light: vec2<f32>,
+    @location(3) ao: f32,
+    @location(4) world_position: vec3<f32>,
+    @location(5) normal: vec3<f32>,
+    // Distance along the camera's view direction, for picking a shadow cascade
+    @location(6) view_depth: f32,
+    // Light level the block gives off, from 0 to 1
+    @location(7) emission: f32
 }
 
 @vertex
@@ -37,21 +50,153 @@ fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
     );
 
     var out: VertexOutput;
-    out.clip_position = camera.view_proj * model_matrix * world_position;
+    let position = model_matrix * world_position;
+    out.clip_position = camera.view_proj * position;
+    out.world_position = position.xyz;
+    out.normal = (model_matrix * vec4<f32>(0.0, 0.0, 1.0, 0.0)).xyz;
+    out.view_depth = out.clip_position.w;
     out.tex_coords = model.tex_coords;
     out.texture_index = instance.texture_index;
+    // Corner light is packed a byte per vertex and occlusion two bits per vertex
+    let light = (instance.light >> (model.index * 8u)) & 255u;
+    out.light = vec2<f32>(f32(light >> 4u), f32(light & 15u));
+    out.ao = f32((instance.ao >> (model.index * 2u)) & 3u);
+    out.emission = f32(instance.emission) / 15.0;
     return out;
 }
 
 
 // Fragment Shader
 
-@group(0) @binding(0)
-var s_diffuse: sampler;
-@group(0) @binding(1)
-var t_diffuse: binding_array<texture_2d<f32>, 3>;
+// Block textures and `sample_block` come from the header the renderer prepends, which depends on
+// how the adapter can bind them
+
+struct ShadowUniform {
+    cascades: array<mat4x4<f32>, 3>,
+    splits: vec4<f32>,
+    texel_sizes: vec4<f32>,
+    light: vec4<f32>
+};
+@group(3) @binding(0)
+var<uniform> shadow: ShadowUniform;
+@group(3) @binding(1)
+var t_shadow: texture_depth_2d_array;
+@group(3) @binding(2)
+var s_shadow: sampler_comparison;
+
+struct PointLight {
+    position: vec3<f32>,
+    radius: f32,
+    color: vec3<f32>,
+    intensity: f32
+};
+struct PointLights {
+    count: u32,
+    lights: array<PointLight>
+};
+@group(3) @binding(3)
+var<storage, read> point_lights: PointLights;
+
+// Sky light in full shadow is dimmed to this share
+const SHADOW_BRIGHTNESS: f32 = 0.6;
+// Glowing blocks are drawn up to this many times brighter than white, which bloom spreads around them
+const EMISSIVE_BOOST: f32 = 2.5;
+
+fn cascade_index(view_depth: f32) -> i32 {
+    for (var i = 0; i < 2; i++) {
+        if view_depth < shadow.splits[i] {
+            return i;
+        }
+    }
+    return 2;
+}
+
+// How much of the sun or moon reaches a surface, from 0 in full shadow to 1
+fn sunlight(position: vec3<f32>, normal: vec3<f32>, view_depth: f32) -> f32 {
+    let facing = dot(normal, shadow.light.xyz);
+    if facing <= 0.0 {
+        return 0.0;
+    }
+
+    let cascade = cascade_index(view_depth);
+    // Pushing the lookup out along the normal keeps surfaces from shadowing themselves
+    let offset = position + normal * shadow.texel_sizes[cascade] * 1.5;
+    let clip = shadow.cascades[cascade] * vec4<f32>(offset, 1.0);
+    let uv = vec2<f32>(clip.x * 0.5 + 0.5, 0.5 - clip.y * 0.5);
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || clip.z > 1.0 {
+        return 1.0;
+    }
+
+    // Percentage closer filtering over a 3x3 texel area softens the shadow edges
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_shadow));
+    var lit = 0.0;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            let sample_uv = uv + vec2<f32>(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(t_shadow, s_shadow, sample_uv, cascade, clip.z);
+        }
+    }
+    return lit / 9.0 * smoothstep(0.0, 0.2, facing);
+}
+
+// Light from the dynamic point lights, fading out towards each light's radius
+fn point_lighting(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    var total = vec3<f32>(0.0);
+    let count = min(point_lights.count, arrayLength(&point_lights.lights));
+    for (var i = 0u; i < count; i++) {
+        let light = point_lights.lights[i];
+        let to_light = light.position - position;
+        let distance = length(to_light);
+        if distance >= light.radius {
+            continue;
+        }
+
+        let falloff = 1.0 - distance / light.radius;
+        // Faces turned away still catch a little of the light, as with block light
+        let facing = max(dot(normal, to_light / max(distance, 0.001)), 0.0);
+        total += light.color * light.intensity * falloff * falloff * (0.4 + 0.6 * facing);
+    }
+    return total;
+}
+
+// Fully occluded corners are darkened to half brightness
+fn ao_brightness(ao: f32) -> f32 {
+    return 0.5 + ao / 6.0;
+}
+
+fn block_color(in: VertexOutput) -> vec4<f32> {
+    // Only sky light is shadowed, and shadows fade out with the sun or moon low in the sky
+    let normal = normalize(in.normal);
+    let sunlit = sunlight(in.world_position, normal, in.view_depth);
+    let shade = mix(1.0, mix(SHADOW_BRIGHTNESS, 1.0, sunlit), shadow.light.w);
+    // Sky light dims with the time of day and takes on the colour of the sky, block light doesn't
+    let sky_light = light_brightness(in.light.x * sky.sun.w) * sky.light_color.rgb * shade;
+    let block_light = vec3<f32>(light_brightness(in.light.y)) + point_lighting(in.world_position, normal);
+    let brightness = max(sky_light, block_light) * ao_brightness(in.ao);
+
+    let color = sample_block(in.texture_index, in.tex_coords);
+
+    let to_surface = in.world_position - camera.eye.xyz;
+    var fog_color = sky.underwater.rgb;
+    if sky.underwater.w == 0.0 {
+        fog_color = atmosphere(normalize(to_surface));
+    }
+    let glow = 1.0 + in.emission * EMISSIVE_BOOST;
+    let lit = mix(color.rgb * brightness * glow, fog_color, fog_amount(length(to_surface)));
+    return vec4<f32>(lit, color.a);
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse[in.texture_index], s_diffuse, in.tex_coords);
-}
\ No newline at end of file
+    return block_color(in);
+}
+
+// Leaves and other blocks with holes, which are either fully drawn or not at all per pixel
+@fragment
+fn fs_cutout(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = block_color(in);
+    if color.a < 0.5 {
+        discard;
+    }
+    return vec4<f32>(color.rgb, 1.0);
+}
@@ -0,0 +1,51 @@
+// Depth of blocks as seen from the light, for one shadow cascade
+
+struct CascadeUniform {
+    view_proj: mat4x4<f32>
+};
+@group(1) @binding(0)
+var<uniform> cascade: CascadeUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>
+}
+
+struct InstanceInput {
+    @location(3) instance_matrix_1: vec4<f32>,
+    @location(4) instance_matrix_2: vec4<f32>,
+    @location(5) instance_matrix_3: vec4<f32>,
+    @location(6) instance_matrix_4: vec4<f32>,
+    @location(7) texture_index: u32
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) texture_index: u32
+}
+
+@vertex
+fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+            instance.instance_matrix_1,
+            instance.instance_matrix_2,
+            instance.instance_matrix_3,
+            instance.instance_matrix_4,
+    );
+
+    var out: VertexOutput;
+    out.clip_position = cascade.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    out.tex_coords = model.tex_coords;
+    out.texture_index = instance.texture_index;
+    return out;
+}
+
+// Light passes through the see-through pixels of leaves, cut the same way as in `fs_cutout` of
+// the block shader
+@fragment
+fn fs_cutout(in: VertexOutput) {
+    if sample_block(in.texture_index, in.tex_coords).a < 0.5 {
+        discard;
+    }
+}
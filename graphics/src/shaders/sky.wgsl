@@ -0,0 +1,164 @@
+#define CAMERA_GROUP 0
+#define SKY_GROUP 1
+#include "camera.wgsl"
+#include "atmosphere.wgsl"
+
+const SUN_COLOR: vec3<f32> = vec3<f32>(1.0, 0.92, 0.75);
+const MOON_COLOR: vec3<f32> = vec3<f32>(0.75, 0.8, 0.9);
+// Half the width of the sun and moon sprites, on the plane one unit away from the camera
+const SUN_SIZE: f32 = 0.06;
+const MOON_SIZE: f32 = 0.045;
+
+// Height the clouds float at, and noise cells per block
+const CLOUD_HEIGHT: f32 = 192.0;
+const CLOUD_SCALE: f32 = 0.02;
+const CLOUD_FADE_DISTANCE: f32 = 1500.0;
+
+// Star grid cells across each unit of direction, and the share of cells holding a star
+const STAR_DENSITY: f32 = 300.0;
+const STAR_CHANCE: f32 = 0.0015;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) screen: vec2<f32>
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One triangle large enough to cover the whole screen
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let screen = uv * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(screen, 1.0, 1.0);
+    out.screen = screen;
+    return out;
+}
+
+fn hash(value: u32) -> u32 {
+    var h = value * 747796405u + 2891336453u;
+    h = ((h >> ((h >> 28u) + 4u)) ^ h) * 277803737u;
+    return (h >> 22u) ^ h;
+}
+
+fn hash2(cell: vec2<i32>) -> f32 {
+    // Cells wrap every 256 so the cloud layer tiles seamlessly
+    let wrapped = vec2<u32>(cell & vec2<i32>(255));
+    return f32(hash(wrapped.x + hash(wrapped.y))) / 4294967295.0;
+}
+
+fn hash3(cell: vec3<i32>) -> f32 {
+    let bits = bitcast<vec3<u32>>(cell);
+    return f32(hash(bits.x + hash(bits.y + hash(bits.z)))) / 4294967295.0;
+}
+
+fn value_noise(position: vec2<f32>) -> f32 {
+    let cell = vec2<i32>(floor(position));
+    let f = fract(position);
+    let t = f * f * (3.0 - 2.0 * f);
+
+    let a = hash2(cell);
+    let b = hash2(cell + vec2<i32>(1, 0));
+    let c = hash2(cell + vec2<i32>(0, 1));
+    let d = hash2(cell + vec2<i32>(1, 1));
+    return mix(mix(a, b, t.x), mix(c, d, t.x), t.y);
+}
+
+fn fbm(position: vec2<f32>) -> f32 {
+    var value = 0.0;
+    var amplitude = 0.5;
+    var p = position;
+    for (var octave = 0; octave < 4; octave++) {
+        value += value_noise(p) * amplitude;
+        p *= 2.0;
+        amplitude *= 0.5;
+    }
+    return value / 0.9375;
+}
+
+// Position of `direction` on a square sprite facing the camera from `towards`, with each axis
+// running from -1 to 1 across the sprite
+fn sprite_position(direction: vec3<f32>, towards: vec3<f32>, size: f32) -> vec2<f32> {
+    let facing = dot(direction, towards);
+    if facing <= 0.0 {
+        return vec2<f32>(2.0);
+    }
+    // Both bodies travel around the z axis, so the sprites stay upright along their path
+    let right = normalize(cross(towards, vec3<f32>(0.0, 0.0, 1.0)));
+    let up = cross(right, towards);
+    return vec2<f32>(dot(direction, right), dot(direction, up)) / (facing * size);
+}
+
+fn inside_sprite(position: vec2<f32>) -> bool {
+    return abs(position.x) < 1.0 && abs(position.y) < 1.0;
+}
+
+fn stars(direction: vec3<f32>) -> f32 {
+    // The night sky turns with the sun and moon
+    let angle = -sky.params.w;
+    let turned = vec3<f32>(
+        direction.x * cos(angle) - direction.y * sin(angle),
+        direction.x * sin(angle) + direction.y * cos(angle),
+        direction.z,
+    );
+
+    let cell = vec3<i32>(floor(turned * STAR_DENSITY));
+    let value = hash3(cell);
+    if value > STAR_CHANCE {
+        return 0.0;
+    }
+    // Fade towards the horizon where the air is thickest
+    return (0.4 + value / STAR_CHANCE * 0.6) * smoothstep(0.0, 0.2, direction.y);
+}
+
+fn clouds(direction: vec3<f32>, color: vec3<f32>) -> vec3<f32> {
+    if abs(direction.y) < 0.01 {
+        return color;
+    }
+    let distance = (CLOUD_HEIGHT - camera.eye.y) / direction.y;
+    if distance <= 0.0 {
+        return color;
+    }
+
+    let position = (camera.eye.xz + direction.xz * distance) * CLOUD_SCALE + sky.params.xy;
+    let cover = sky.zenith.w;
+    let density = smoothstep(1.0 - cover, 1.0 - cover + 0.25, fbm(position));
+    let fade = exp(-distance / CLOUD_FADE_DISTANCE);
+
+    // Lit by the sky, grey at night and tinted at sunrise and sunset
+    let cloud_color = sky.light_color.rgb * mix(0.04, 1.0, (sky.sun.w - 0.2) / 0.8);
+    return mix(color, cloud_color, density * fade * 0.9);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Nothing beyond the fluid can be seen from inside it
+    if sky.underwater.w > 0.0 {
+        return vec4<f32>(sky.underwater.rgb, 1.0);
+    }
+
+    let far = camera.inv_view_proj * vec4<f32>(in.screen, 1.0, 1.0);
+    let direction = normalize(far.xyz / far.w - camera.eye.xyz);
+
+    var color = atmosphere(direction);
+    color += vec3<f32>(stars(direction) * sky.params.z);
+
+    let sun = sprite_position(direction, sky.sun.xyz, SUN_SIZE);
+    if inside_sprite(sun) {
+        color = SUN_COLOR;
+    }
+
+    let moon = sprite_position(direction, sky.moon.xyz, MOON_SIZE);
+    if inside_sprite(moon) {
+        // The lit part shrinks from the right towards the new moon and grows back after it
+        let lit = abs(sky.moon.w * 2.0 - 1.0);
+        if moon.x < lit * 2.0 - 1.0 {
+            color = MOON_COLOR;
+        } else {
+            color = mix(color, MOON_COLOR, 0.08);
+        }
+    }
+
+    color = clouds(direction, color);
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,177 @@
+// Water surface, with waves, reflections of the sky and a tint that deepens with the water behind it
+
+#define CAMERA_GROUP 0
+#define SKY_GROUP 1
+#include "camera.wgsl"
+#include "atmosphere.wgsl"
+#include "lighting.wgsl"
+
+struct WaterUniform {
+    // Seconds of world time in x
+    params: vec4<f32>
+};
+@group(2) @binding(0)
+var<uniform> water: WaterUniform;
+// Depth of everything drawn before the water, read as plain floats since GL can't load from
+// depth textures
+@group(2) @binding(1)
+var t_scene_depth: texture_2d<f32>;
+
+// How far the waves reach below the surface, in blocks
+const WAVE_HEIGHT: f32 = 0.08;
+// Blocks per second the ripples drift along the flow of the water
+const FLOW_SPEED: f32 = 1.2;
+// Colour of deep water, and how quickly light fades through it per block
+const DEEP_COLOR: vec3<f32> = vec3<f32>(0.02, 0.09, 0.18);
+const SHALLOW_COLOR: vec3<f32> = vec3<f32>(0.1, 0.35, 0.45);
+const ABSORPTION: f32 = 0.35;
+
+struct VertexInput {
+    @builtin(vertex_index) index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>
+}
+
+struct InstanceInput {
+    @location(3) instance_matrix_1: vec4<f32>,
+    @location(4) instance_matrix_2: vec4<f32>,
+    @location(5) instance_matrix_3: vec4<f32>,
+    @location(6) instance_matrix_4: vec4<f32>,
+    @location(8) light: u32,
+    @location(10) fluid: u32
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) light: vec2<f32>,
+    @location(3) flow: vec2<f32>,
+    // Whether the face is open water that waves, as 1 or 0
+    @location(4) surface: f32
+}
+
+// Height of the waves at a point, from 0 at the troughs to 1 at the crests
+fn wave_height(position: vec2<f32>) -> f32 {
+    let time = water.params.x;
+    let a = sin(dot(position, vec2<f32>(0.8, 0.6)) * 1.3 + time * 1.1);
+    let b = sin(dot(position, vec2<f32>(-0.5, 0.9)) * 1.9 + time * 1.6);
+    return (a + b) * 0.25 + 0.5;
+}
+
+@vertex
+fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+            instance.instance_matrix_1,
+            instance.instance_matrix_2,
+            instance.instance_matrix_3,
+            instance.instance_matrix_4,
+    );
+    var position = model_matrix * vec4<f32>(model.position, 1.0);
+    let normal = (model_matrix * vec4<f32>(0.0, 0.0, 1.0, 0.0)).xyz;
+
+    // Flow is packed as two signed bytes, followed by the surface height
+    let flow = vec2<f32>(
+        f32(bitcast<i32>(instance.fluid << 24u) >> 24u),
+        f32(bitcast<i32>(instance.fluid << 16u) >> 24u),
+    ) / 127.0;
+    let height = f32((instance.fluid >> 16u) & 255u) / 255.0;
+    let surface = height < 1.0;
+
+    // The top face, and the upper edge of the sides, drop to the surface and move with the waves.
+    // Neighbouring faces share these points, so the surface stays closed
+    let top = normal.y > 0.5 || (abs(normal.y) < 0.5 && model.position.y > 0.0);
+    if top && surface {
+        position.y -= 1.0 - height + wave_height(position.xz) * WAVE_HEIGHT;
+    }
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * position;
+    out.world_position = position.xyz;
+    out.normal = normal;
+    let light = (instance.light >> (model.index * 8u)) & 255u;
+    out.light = vec2<f32>(f32(light >> 4u), f32(light & 15u));
+    out.flow = flow;
+    out.surface = select(0.0, 1.0, surface && normal.y > 0.5);
+    return out;
+}
+
+// Slope of the waves plus small ripples drifting along the flow, turned into a surface normal
+fn wave_normal(position: vec2<f32>, flow: vec2<f32>) -> vec3<f32> {
+    let time = water.params.x;
+    let step = 0.05;
+    let slope = vec2<f32>(
+        wave_height(position + vec2<f32>(step, 0.0)) - wave_height(position - vec2<f32>(step, 0.0)),
+        wave_height(position + vec2<f32>(0.0, step)) - wave_height(position - vec2<f32>(0.0, step)),
+    ) / (2.0 * step) * WAVE_HEIGHT;
+
+    // Still water ripples in place, flowing water carries its ripples along
+    let drift = position - flow * time * FLOW_SPEED;
+    let ripple = vec2<f32>(
+        cos(drift.x * 5.1 + drift.y * 1.7 + time * 2.3) + cos(drift.x * 8.3 - drift.y * 3.1 - time * 1.7) * 0.5,
+        cos(drift.y * 4.7 - drift.x * 2.3 + time * 2.9) + cos(drift.y * 7.9 + drift.x * 3.7 + time * 1.3) * 0.5,
+    ) * 0.04;
+
+    return normalize(vec3<f32>(-slope.x - ripple.x, 1.0, -slope.y - ripple.y));
+}
+
+// Distance through the water along the view ray to whatever is behind it
+fn water_depth(in: VertexOutput) -> f32 {
+    let size = vec2<f32>(textureDimensions(t_scene_depth));
+    let depth = textureLoad(t_scene_depth, vec2<i32>(in.clip_position.xy), 0).x;
+    if depth >= 1.0 {
+        return 1000.0;
+    }
+
+    let uv = in.clip_position.xy / size;
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let scene = camera.inv_view_proj * ndc;
+    let behind = distance(scene.xyz / scene.w, camera.eye.xyz);
+    return max(behind - distance(in.world_position, camera.eye.xyz), 0.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let to_eye = camera.eye.xyz - in.world_position;
+    let view = normalize(to_eye);
+
+    var normal = normalize(in.normal);
+    if in.surface > 0.5 {
+        normal = wave_normal(in.world_position.xz, in.flow);
+    }
+    // Seen from below, the surface faces the camera the other way
+    if dot(normal, view) < 0.0 {
+        normal = -normal;
+    }
+
+    // Lit like the blocks around it, by the sky and by block light
+    let sky_light = light_brightness(in.light.x * sky.sun.w) * sky.light_color.rgb;
+    let block_light = vec3<f32>(light_brightness(in.light.y));
+    let brightness = max(sky_light, block_light);
+
+    // Shallow water shows what is beneath it, deep water turns dark and opaque
+    let absorbed = 1.0 - exp(-water_depth(in) * ABSORPTION);
+    let body = mix(SHALLOW_COLOR, DEEP_COLOR, absorbed) * brightness;
+    var alpha = mix(0.25, 0.92, absorbed);
+
+    // Schlick's approximation, reflecting more of the sky at grazing angles
+    let fresnel = 0.02 + 0.98 * pow(1.0 - max(dot(normal, view), 0.0), 5.0);
+    let reflected = reflect(-view, normal);
+    var reflection = atmosphere(vec3<f32>(reflected.x, abs(reflected.y), reflected.z));
+    let sun_up = smoothstep(-0.05, 0.1, sky.sun.y);
+    reflection += sky.light_color.rgb * pow(max(dot(reflected, sky.sun.xyz), 0.0), 200.0) * 4.0 * sun_up;
+    // Only the open sky is reflected, so covered water reflects little
+    reflection *= light_brightness(in.light.x);
+
+    var color = mix(body, reflection, fresnel);
+    alpha = mix(alpha, 1.0, fresnel);
+
+    var fog_color = sky.underwater.rgb;
+    if sky.underwater.w == 0.0 {
+        fog_color = atmosphere(-view);
+    }
+    let fog = fog_amount(length(to_eye));
+    color = mix(color, fog_color, fog);
+    alpha = mix(alpha, 1.0, fog);
+    return vec4<f32>(color, alpha);
+}
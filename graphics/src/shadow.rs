@@ -0,0 +1,325 @@
+use std::ops::Range;
+
+use nalgebra::{Matrix4, Point3, Translation3, Vector3, Vector4};
+use wgpu::{BindGroup, BindGroupLayout, Buffer};
+use wgpu::util::DeviceExt;
+
+use world::clock::WorldClock;
+
+use crate::block_textures::BlockTextures;
+use crate::camera::{Camera, OPENGL_TO_WGPU_MATRIX};
+use crate::graphics::Graphics;
+use crate::lights::PointLights;
+use crate::pipeline::Pipeline;
+use crate::quad;
+use crate::texture::Texture;
+use crate::Vertex;
+
+pub const CASCADES: usize = 3;
+const SHADOW_MAP_SIZE: u32 = 2048;
+// Blend between logarithmic splits, which suit perspective, and even ones
+const SPLIT_LAMBDA: f32 = 0.6;
+// How far towards the light beyond a cascade blocks can still cast shadows into it
+const CASTER_DISTANCE: f32 = 96.;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CascadeUniform {
+    view_proj: [[f32; 4]; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowUniform {
+    cascades: [[[f32; 4]; 4]; CASCADES],
+    // View depth each cascade reaches up to
+    splits: [f32; 4],
+    // Size of a shadow map texel in blocks, for each cascade
+    texel_sizes: [f32; 4],
+    // Direction towards the light casting shadows, with the shadow strength in w
+    light: [f32; 4],
+}
+
+/**
+ * Depth of the world as seen from the sun, or the moon at night, split into cascades that
+ * cover successively further slices of the camera's view.
+ */
+pub struct Shadows {
+    uniform: ShadowUniform,
+    buffer: Buffer,
+    cascade_buffers: Vec<Buffer>,
+    cascade_bind_groups: Vec<BindGroup>,
+    shadow_map: Texture,
+    pub bind_group: BindGroup,
+    pub bind_group_layout: BindGroupLayout,
+    pipeline: Pipeline,
+    // Leaves and other cutout blocks only cast shadows where their textures aren't see-through
+    cutout_pipeline: Pipeline,
+}
+
+/**
+ * View depths splitting the camera frustum into cascades, starting with the near plane.
+ */
+fn split_distances(near: f32, far: f32) -> [f32; CASCADES + 1] {
+    let mut splits = [0.; CASCADES + 1];
+    for (i, split) in splits.iter_mut().enumerate() {
+        let t = i as f32 / CASCADES as f32;
+        let logarithmic = near * (far / near).powf(t);
+        let even = near + (far - near) * t;
+        *split = SPLIT_LAMBDA * logarithmic + (1. - SPLIT_LAMBDA) * even;
+    }
+    splits
+}
+
+/**
+ * An orthographic projection from the light around a bounding sphere of the frustum slice.
+ * A sphere keeps the size of the cascade constant as the camera turns, and snapping it to
+ * whole texels stops shadow edges from crawling as the camera moves.
+ */
+fn cascade_matrix(corners: &[Point3<f32>; 8], light: Vector3<f32>) -> (Matrix4<f32>, f32) {
+    let center = corners.iter().fold(Vector3::zeros(), |sum, corner| sum + corner.coords) / 8.;
+    let radius = corners.iter().map(|corner| (corner.coords - center).norm()).fold(0., f32::max);
+    let radius = (radius * 16.).ceil() / 16.;
+
+    let center = Point3::from(center);
+    let eye = center + light * (radius + CASTER_DISTANCE);
+    let view = Matrix4::look_at_rh(&eye, &center, &Vector3::z());
+    let projection = Matrix4::new_orthographic(-radius, radius, -radius, radius, 0., radius * 2. + CASTER_DISTANCE);
+    let matrix = OPENGL_TO_WGPU_MATRIX * projection * view;
+
+    let half_size = SHADOW_MAP_SIZE as f32 / 2.;
+    let origin = matrix * Vector4::new(0., 0., 0., 1.) * half_size;
+    let offset = Translation3::new((origin.x.round() - origin.x) / half_size, (origin.y.round() - origin.y) / half_size, 0.);
+
+    (offset.to_homogeneous() * matrix, radius * 2. / SHADOW_MAP_SIZE as f32)
+}
+
+impl Shadows {
+    /**
+     * The point lights are bound alongside the shadow maps, the block pipeline having no bind
+     * groups to spare.
+     */
+    pub fn new(graphics: &Graphics, point_lights: &PointLights, block_textures: &BlockTextures, shader: &str) -> Self {
+        let shadow_map = Texture::create_shadow_map(&graphics.device, SHADOW_MAP_SIZE, CASCADES as u32, "shadow_map");
+
+        let uniform = ShadowUniform {
+            cascades: [Matrix4::identity().into(); CASCADES],
+            splits: [0.; 4],
+            texel_sizes: [0.; 4],
+            light: [0., 1., 0., 0.],
+        };
+
+        let buffer = graphics.device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Shadow Buffer"),
+                contents: bytemuck::cast_slice(&[uniform]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let bind_group_layout = graphics.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("shadow_bind_group_layout"),
+        });
+
+        let bind_group = graphics.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&shadow_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&shadow_map.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: point_lights.buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("shadow_bind_group"),
+        });
+
+        let cascade_bind_group_layout = graphics.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("cascade_bind_group_layout"),
+        });
+
+        let cascade_buffers: Vec<Buffer> = (0..CASCADES)
+            .map(|_| graphics.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Cascade Buffer"),
+                contents: bytemuck::cast_slice(&[CascadeUniform { view_proj: Matrix4::identity().into() }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }))
+            .collect();
+
+        let cascade_bind_groups = cascade_buffers.iter()
+            .map(|buffer| graphics.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &cascade_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }
+                ],
+                label: Some("cascade_bind_group"),
+            }))
+            .collect();
+
+        let pipeline_layout = graphics.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&block_textures.bind_group_layout, &cascade_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader = format!("{}\n{}", block_textures.shader_header, shader);
+        let [pipeline, cutout_pipeline] = [("Shadow", None), ("Cutout Shadow", Some("fs_cutout"))]
+            .map(|(label, fragment_entry)| Pipeline::depth_only(
+                graphics,
+                label,
+                &shader,
+                fragment_entry,
+                Vertex::init_buffer_layout(),
+                Some(&pipeline_layout),
+            ));
+
+        Self {
+            uniform,
+            buffer,
+            cascade_buffers,
+            cascade_bind_groups,
+            shadow_map,
+            bind_group,
+            bind_group_layout,
+            pipeline,
+            cutout_pipeline,
+        }
+    }
+
+    /**
+     * Fits the cascades around the camera's view. Shadows are cast by the sun by day and the
+     * moon by night, fading out as either nears the horizon where shadows would grow endless.
+     */
+    pub fn update(&mut self, graphics: &Graphics, camera: &Camera, clock: &WorldClock, enabled: bool) {
+        let sun = clock.sun_direction();
+        let direction = if sun[1] >= 0. { sun } else { clock.moon_direction() };
+        let light = Vector3::from(direction);
+        let strength = if enabled { (light.y / 0.15).clamp(0., 1.) } else { 0. };
+
+        let splits = split_distances(camera.near, camera.far);
+        for cascade in 0..CASCADES {
+            let corners = camera.frustum_corners(splits[cascade], splits[cascade + 1]);
+            let (matrix, texel_size) = cascade_matrix(&corners, light);
+
+            self.uniform.cascades[cascade] = matrix.into();
+            self.uniform.splits[cascade] = splits[cascade + 1];
+            self.uniform.texel_sizes[cascade] = texel_size;
+            graphics.queue.write_buffer(&self.cascade_buffers[cascade], 0, bytemuck::cast_slice(&[CascadeUniform { view_proj: matrix.into() }]));
+        }
+        self.uniform.light = [light.x, light.y, light.z, strength];
+        graphics.queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+
+    /**
+     * Renders the depth of the opaque and then the cutout quads in `instances` into each cascade.
+     */
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        block_textures: &BindGroup,
+        vertex_buffer: &Buffer,
+        instance_buffer: &Buffer,
+        index_buffer: &Buffer,
+        instances: &[Range<u32>; 2],
+    ) {
+        for (layer, bind_group) in self.cascade_bind_groups.iter().enumerate() {
+            let view = self.shadow_map.texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("shadow_map_layer"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer as u32,
+                array_layer_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            });
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            if self.uniform.light[3] == 0. {
+                continue;
+            }
+
+            render_pass.set_bind_group(0, block_textures, &[]);
+            render_pass.set_bind_group(1, bind_group, &[]);
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+            render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            for (pipeline, instances) in [&self.pipeline, &self.cutout_pipeline].into_iter().zip(instances) {
+                render_pass.set_pipeline(&pipeline.pipeline);
+                // Which diagonal a quad is split along doesn't change its depth
+                render_pass.draw_indexed(0..quad::INDICES.len() as u32, 0, instances.clone());
+            }
+        }
+    }
+}
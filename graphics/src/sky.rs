@@ -0,0 +1,190 @@
+use std::f32::consts::TAU;
+
+use wgpu::{BindGroup, BindGroupLayout, Buffer};
+use wgpu::util::DeviceExt;
+
+use world::clock::{TICKS_PER_SECOND, WorldClock};
+
+use crate::graphics::Graphics;
+use crate::pipeline::Pipeline;
+use crate::settings::{FogMode, Settings};
+
+// Sky colours in linear space, at the zenith and the horizon
+const DAY_ZENITH: [f32; 3] = [0.1, 0.3, 0.8];
+const DAY_HORIZON: [f32; 3] = [0.45, 0.65, 0.9];
+const NIGHT_ZENITH: [f32; 3] = [0.001, 0.002, 0.008];
+const NIGHT_HORIZON: [f32; 3] = [0.005, 0.008, 0.02];
+
+// Colour of sky light on blocks
+const DAY_LIGHT: [f32; 3] = [1., 1., 1.];
+const NIGHT_LIGHT: [f32; 3] = [0.6, 0.7, 1.];
+const SUNSET_LIGHT: [f32; 3] = [1., 0.7, 0.45];
+
+// Clouds drift with the world clock, in noise cells per second
+const CLOUD_SPEED: [f64; 2] = [0.02, 0.007];
+// The cloud noise repeats every this many cells, so the drift can wrap around without a seam
+const CLOUD_PERIOD: f64 = 256.;
+const CLOUD_COVER: f32 = 0.45;
+// Fog colour and density in blocks while the camera is inside a fluid
+const UNDERWATER_COLOR: [f32; 3] = [0.02, 0.08, 0.2];
+const UNDERWATER_DENSITY: f32 = 0.15;
+// Exponential fog has thinned the view to this share at the fog end
+const FOG_REMAINING: f32 = 1. / 256.;
+
+// Days it takes the moon to go through its phases
+const MOON_CYCLE: u64 = 8;
+
+fn mix(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t]
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkyUniform {
+    // Direction towards the sun, with the daylight factor for sky light in w
+    sun: [f32; 4],
+    // Direction towards the moon, with its phase from 0 (full) to 1 in w
+    moon: [f32; 4],
+    // Zenith colour, with how much of the sky is covered by clouds in w
+    zenith: [f32; 4],
+    // Horizon colour, with the strength of the sunrise and sunset glow in w
+    horizon: [f32; 4],
+    light_color: [f32; 4],
+    // Cloud offset in xy, star visibility in z and the angle the night sky has turned by in w
+    params: [f32; 4],
+    // Fog start and end distance, density for exponential fog and the `FogMode`
+    fog: [f32; 4],
+    // Fog colour while submerged, with the density in w or 0 when the camera is not in a fluid
+    underwater: [f32; 4],
+}
+
+impl SkyUniform {
+    pub fn new(clock: &WorldClock, settings: &Settings, underwater: bool) -> Self {
+        let sun = clock.sun_direction();
+        let daylight = clock.daylight();
+        // How much of the day colours show, reaching full day a while after sunrise
+        let day = ((sun[1] + 0.1) / 0.4).clamp(0., 1.);
+        // Strongest with the sun right on the horizon
+        let glow = (1. - sun[1].abs() / 0.35).clamp(0., 1.).powi(2);
+
+        let zenith = mix(NIGHT_ZENITH, DAY_ZENITH, day);
+        let horizon = mix(NIGHT_HORIZON, DAY_HORIZON, day);
+        let light_color = mix(mix(NIGHT_LIGHT, DAY_LIGHT, day), SUNSET_LIGHT, glow);
+
+        let moon = clock.moon_direction();
+        let phase = (clock.day() % MOON_CYCLE) as f32 / MOON_CYCLE as f32;
+
+        let seconds = clock.ticks() / TICKS_PER_SECOND;
+        let clouds = CLOUD_SPEED.map(|speed| (seconds * speed % CLOUD_PERIOD) as f32);
+        let stars = (1. - day).powi(4);
+
+        let (fog_start, fog_end) = settings.fog_range();
+        let fog_density = -FOG_REMAINING.ln() / (fog_end - fog_start);
+        let fog_mode = match settings.fog {
+            FogMode::Off => 0.,
+            FogMode::Linear => 1.,
+            FogMode::Exponential => 2.,
+        };
+        let underwater_density = if underwater { UNDERWATER_DENSITY } else { 0. };
+
+        Self {
+            sun: [sun[0], sun[1], sun[2], daylight],
+            moon: [moon[0], moon[1], moon[2], phase],
+            zenith: [zenith[0], zenith[1], zenith[2], CLOUD_COVER],
+            horizon: [horizon[0], horizon[1], horizon[2], glow],
+            light_color: [light_color[0], light_color[1], light_color[2], 1.],
+            params: [clouds[0], clouds[1], stars, clock.day_fraction() * TAU],
+            fog: [fog_start, fog_end, fog_density, fog_mode],
+            underwater: [UNDERWATER_COLOR[0], UNDERWATER_COLOR[1], UNDERWATER_COLOR[2], underwater_density],
+        }
+    }
+}
+
+/**
+ * The sky dome behind the world, with the sun, moon, stars and clouds, and the colour of the
+ * light and fog it casts, all following the world clock.
+ */
+pub struct Sky {
+    uniform: SkyUniform,
+    buffer: Buffer,
+    pub bind_group: BindGroup,
+    pub bind_group_layout: BindGroupLayout,
+    pipeline: Pipeline,
+}
+
+impl Sky {
+    pub fn new(graphics: &Graphics, clock: &WorldClock, settings: &Settings, camera_bind_group_layout: &BindGroupLayout, shader: &str) -> Self {
+        let uniform = SkyUniform::new(clock, settings, false);
+
+        let buffer = graphics.device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Sky Buffer"),
+                contents: bytemuck::cast_slice(&[uniform]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let bind_group_layout = graphics.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("sky_bind_group_layout"),
+        });
+
+        let bind_group = graphics.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }
+            ],
+            label: Some("sky_bind_group"),
+        });
+
+        let pipeline_layout = graphics.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sky Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = Pipeline::fullscreen(graphics, "Sky", shader, Some(&pipeline_layout));
+
+        Self {
+            uniform,
+            buffer,
+            bind_group,
+            bind_group_layout,
+            pipeline,
+        }
+    }
+
+    pub fn update(&mut self, graphics: &Graphics, clock: &WorldClock, settings: &Settings, underwater: bool) {
+        self.uniform = SkyUniform::new(clock, settings, underwater);
+        graphics.queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+
+    pub fn clear_color(&self) -> wgpu::Color {
+        let [r, g, b, _] = self.uniform.horizon;
+        wgpu::Color { r: r as f64, g: g as f64, b: b as f64, a: 1.0 }
+    }
+
+    /**
+     * Fills the background, to be drawn before anything else in the pass.
+     */
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a BindGroup) {
+        render_pass.set_pipeline(&self.pipeline.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
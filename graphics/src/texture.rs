@@ -1,4 +1,10 @@
-use image::GenericImageView;
+use std::num::NonZeroU8;
+
+use image::RgbaImage;
+use image::imageops::FilterType;
+
+// Alpha below which the cutout shader discards a pixel
+pub(crate) const ALPHA_CUTOFF: u8 = 128;
 
 pub struct Texture {
     pub texture: wgpu::Texture,
@@ -8,25 +14,40 @@ pub struct Texture {
 
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    // The scene is drawn with brightness above 1 kept, for bloom and tonemapping to work with
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
 
-    pub fn from_bytes(device: &wgpu::Device, queue: &wgpu::Queue, bytes: &[u8], label: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+    /**
+     * A block texture, sampled with up to `anisotropy` texels along surfaces seen at an angle.
+     */
+    pub fn from_rgba(device: &wgpu::Device, queue: &wgpu::Queue, image: &RgbaImage, anisotropy: u8, label: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_layers(device, queue, &[image], anisotropy, Some(label), wgpu::TextureViewDimension::D2)
     }
 
-    pub fn from_image(device: &wgpu::Device, queue: &wgpu::Queue, img: &image::DynamicImage, label: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
-        let rgba = img.to_rgba8();
-        let dimensions = img.dimensions();
+    /**
+     * Stacks images of the same size into the layers of one array texture, in order.
+     */
+    pub fn array_from_images(device: &wgpu::Device, queue: &wgpu::Queue, images: &[&RgbaImage], anisotropy: u8, label: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_layers(device, queue, images, anisotropy, Some(label), wgpu::TextureViewDimension::D2Array)
+    }
+
+    // A texture with a full mip chain generated for every layer
+    fn from_layers(device: &wgpu::Device, queue: &wgpu::Queue, images: &[&RgbaImage], anisotropy: u8, label: Option<&str>, dimension: wgpu::TextureViewDimension) -> Result<Self, Box<dyn std::error::Error>> {
+        let (width, height) = images.first().ok_or("A texture needs at least one image")?.dimensions();
+        if let Some(image) = images.iter().find(|image| image.dimensions() != (width, height)) {
+            let (other_width, other_height) = image.dimensions();
+            return Err(format!("Images of {} must all be {}x{}, found one of {}x{}", label.unwrap_or("texture"), width, height, other_width, other_height).into());
+        }
 
         let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
+            width,
+            height,
+            depth_or_array_layers: images.len() as u32,
         };
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
             size,
-            mip_level_count: 1,
+            mip_level_count: size.max_mips(wgpu::TextureDimension::D2),
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
@@ -34,24 +55,50 @@ impl Texture {
             view_formats: &[],
         });
 
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: std::num::NonZeroU32::new(4 * dimensions.0),
-                rows_per_image: None, // std::num::NonZeroU32::new(dimensions.1)
-            },
-            size,
-        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(dimension),
+            ..Default::default()
+        });
+        let sampler = Self::create_block_sampler(device, anisotropy);
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(
+        let texture = Self { texture, view, sampler };
+        for (layer, image) in images.iter().enumerate() {
+            texture.write_layer(queue, layer as u32, image);
+        }
+        Ok(texture)
+    }
+
+    /**
+     * Replaces one layer with an image of the texture's size, along with its mip chain.
+     */
+    pub fn write_layer(&self, queue: &wgpu::Queue, layer: u32, image: &RgbaImage) {
+        for (level, mip) in mip_chain(image).iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &self.texture,
+                    mip_level: level as u32,
+                    origin: wgpu::Origin3d { x: 0, y: 0, z: layer },
+                },
+                mip,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(4 * mip.width()),
+                    rows_per_image: None,
+                },
+                wgpu::Extent3d {
+                    width: mip.width(),
+                    height: mip.height(),
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+
+    // Sharp texels up close, blended ones in the distance
+    // Anisotropy of 1 turns it off, and adapters without anisotropic filtering ignore it
+    fn create_block_sampler(device: &wgpu::Device, anisotropy: u8) -> wgpu::Sampler {
+        device.create_sampler(
             &wgpu::SamplerDescriptor {
                 address_mode_u: wgpu::AddressMode::Repeat,
                 address_mode_v: wgpu::AddressMode::Repeat,
@@ -59,11 +106,10 @@ impl Texture {
                 mag_filter: wgpu::FilterMode::Nearest,
                 min_filter: wgpu::FilterMode::Linear,
                 mipmap_filter: wgpu::FilterMode::Linear,
+                anisotropy_clamp: NonZeroU8::new(anisotropy).filter(|anisotropy| anisotropy.get() > 1),
                 ..Default::default()
             }
-        );
-
-        Ok(Self { texture, view, sampler })
+        )
     }
 
     pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, label: &str) -> Self {
@@ -72,6 +118,58 @@ impl Texture {
             height: config.height,
             depth_or_array_layers: 1,
         };
+        Self::create_depth(device, size, wgpu::TextureViewDimension::D2, label)
+    }
+
+    /**
+     * A colour texture that passes render into and later ones sample from, with its own linear
+     * sampler clamped to the edges. It can be copied out, to read frames back.
+     */
+    pub fn create_render_target(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat, label: &str) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self { texture, view, sampler }
+    }
+
+    /**
+     * A square depth texture with one layer per shadow cascade, viewed as an array for sampling.
+     */
+    pub fn create_shadow_map(device: &wgpu::Device, size: u32, layers: u32, label: &str) -> Self {
+        let size = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: layers,
+        };
+        Self::create_depth(device, size, wgpu::TextureViewDimension::D2Array, label)
+    }
+
+    fn create_depth(device: &wgpu::Device, size: wgpu::Extent3d, dimension: wgpu::TextureViewDimension, label: &str) -> Self {
         let desc = wgpu::TextureDescriptor {
             label: Some(label),
             size,
@@ -85,7 +183,10 @@ impl Texture {
         };
         let texture = device.create_texture(&desc);
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(dimension),
+            ..Default::default()
+        });
         let sampler = device.create_sampler(
             &wgpu::SamplerDescriptor { // 4.
                 address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -103,4 +204,100 @@ impl Texture {
 
         Self { texture, view, sampler }
     }
-}
\ No newline at end of file
+}
+
+/**
+ * Each level half the size of the one before, down to a single pixel. Images whose pixels are
+ * only ever fully opaque or fully transparent are taken to be alpha tested, and have the alpha of
+ * every level scaled so the same share of it passes the cutoff as of the full size image. Plain
+ * averaging would otherwise fade leaves away into nothing in the distance.
+ */
+fn mip_chain(image: &RgbaImage) -> Vec<RgbaImage> {
+    let alpha_tested = image.pixels().all(|pixel| pixel.0[3] == 0 || pixel.0[3] == 255)
+        && image.pixels().any(|pixel| pixel.0[3] == 0);
+    let target = coverage(image, 1.0);
+
+    let mut levels = vec![image.clone()];
+    // Each level is made from the one before as it was averaged, so the scaling doesn't compound
+    let mut last = image.clone();
+    while last.width() > 1 || last.height() > 1 {
+        let (width, height) = ((last.width() / 2).max(1), (last.height() / 2).max(1));
+        last = image::imageops::resize(&last, width, height, FilterType::Triangle);
+        let mut level = last.clone();
+        if alpha_tested {
+            let scale = coverage_scale(&level, target);
+            for pixel in level.pixels_mut() {
+                pixel.0[3] = (pixel.0[3] as f32 * scale).min(255.0) as u8;
+            }
+        }
+        levels.push(level);
+    }
+    levels
+}
+
+// Share of the pixels that pass the cutoff once their alpha is scaled
+fn coverage(image: &RgbaImage, scale: f32) -> f32 {
+    let passing = image.pixels().filter(|pixel| pixel.0[3] as f32 * scale >= ALPHA_CUTOFF as f32).count();
+    passing as f32 / image.pixels().len() as f32
+}
+
+// Coverage only grows with the scale, so it can be found by bisection
+fn coverage_scale(image: &RgbaImage, target: f32) -> f32 {
+    let (mut low, mut high) = (0.0f32, 255.0f32);
+    for _ in 0..16 {
+        let middle = (low + high) / 2.0;
+        if coverage(image, middle) < target {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    high
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    // Leaves with a scattering of holes, from a fixed pseudo random sequence
+    fn sparse_leaves(size: u32) -> RgbaImage {
+        let mut state = 12345u32;
+        RgbaImage::from_fn(size, size, |_, _| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            let alpha = if (state >> 16) % 5 < 3 { 255 } else { 0 };
+            Rgba([40, 120, 30, alpha])
+        })
+    }
+
+    #[test]
+    fn keeps_the_coverage_of_alpha_tested_images() {
+        let image = sparse_leaves(32);
+        let target = coverage(&image, 1.0);
+        let levels = mip_chain(&image);
+        assert_eq!(levels.len(), 6);
+
+        for (i, level) in levels.iter().enumerate() {
+            let coverage = coverage(level, 1.0);
+            assert!(coverage >= target, "level {} covers {} instead of {}", i, coverage, target);
+            // Pixels of the smallest levels often average to the same alpha and pass or fail
+            // together, so only the larger ones can match closely
+            if level.pixels().len() >= 64 {
+                assert!(coverage - target <= 0.05, "level {} covers {} instead of {}", i, coverage, target);
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_partial_alpha_unscaled() {
+        let image = RgbaImage::from_fn(8, 8, |x, y| Rgba([200, 100, 50, (x * 32 + y) as u8]));
+        let levels = mip_chain(&image);
+
+        let mut expected = image.clone();
+        for level in &levels[1..] {
+            expected = image::imageops::resize(&expected, expected.width() / 2, expected.height() / 2, FilterType::Triangle);
+            assert_eq!(level, &expected);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::ops::Range;
+
+use nalgebra::Point3;
+use wgpu::Buffer;
+
+use math::point::Coord3DI;
+use math::quad::Quad;
+
+use crate::culling::{Aabb, CullingStats, SectionPos};
+use crate::graphics::Graphics;
+use crate::instance::InstanceRaw;
+use crate::quad::Raw;
+
+/**
+ * Translucent quads, like glass and water, which have to be drawn from back to front to blend
+ * correctly. They are kept per section and re-sorted into the instance buffer whenever the camera
+ * moves into another block, and sections culled are left out when drawing.
+ */
+pub struct TranslucentQuads {
+    sections: Vec<(SectionPos, Aabb, Vec<Quad>)>,
+    buffer: Buffer,
+    num_quads: u32,
+    // Block the camera was in when the quads were last sorted
+    sorted_from: Option<Coord3DI>,
+    // Where each section's quads are in the buffer, in the order they were sorted into
+    sorted: Vec<(SectionPos, Aabb, Range<u32>)>,
+    // Instances to draw, with neighbouring sections in view merged into one range
+    visible: Vec<Range<u32>>,
+}
+
+fn distance_squared(eye: Point3<f32>, x: f32, y: f32, z: f32) -> f32 {
+    (x - eye.x).powi(2) + (y - eye.y).powi(2) + (z - eye.z).powi(2)
+}
+
+impl TranslucentQuads {
+    pub fn new(graphics: &Graphics, sections: Vec<(SectionPos, Vec<Quad>)>) -> Self {
+        let sections: Vec<_> = sections.into_iter()
+            .filter_map(|(pos, quads)| Aabb::around(&quads).map(|bounds| (pos, bounds, quads)))
+            .collect();
+        let num_quads = sections.iter().map(|(_, _, quads)| quads.len()).sum::<usize>() as u32;
+
+        let buffer = graphics.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Translucent Instance Buffer"),
+            // Buffers can't be empty
+            size: (num_quads.max(1) as usize * std::mem::size_of::<InstanceRaw>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            sections,
+            buffer,
+            num_quads,
+            sorted_from: None,
+            sorted: vec![],
+            visible: vec![],
+        }
+    }
+
+    /**
+     * Orders sections and the quads within each section from furthest to closest to `eye`.
+     */
+    pub fn sort(&mut self, graphics: &Graphics, eye: Point3<f32>) {
+        let cell = Coord3DI::new(eye.x.floor() as i32, eye.y.floor() as i32, eye.z.floor() as i32);
+        if self.sorted_from == Some(cell) {
+            return;
+        }
+        self.sorted_from = Some(cell);
+
+        let section_distance = |pos: &SectionPos| {
+            let bounds = pos.bounds();
+            let center = nalgebra::center(&bounds.min, &bounds.max);
+            distance_squared(eye, center.x, center.y, center.z)
+        };
+        let quad_distance = |quad: &Quad| distance_squared(eye, quad.pos.x, quad.pos.y, quad.pos.z);
+
+        let mut sections: Vec<&(SectionPos, Aabb, Vec<Quad>)> = self.sections.iter().collect();
+        sections.sort_by(|(a, _, _), (b, _, _)| section_distance(b).total_cmp(&section_distance(a)));
+
+        let mut instance_data = Vec::with_capacity(self.num_quads as usize);
+        self.sorted.clear();
+        for (pos, bounds, quads) in sections {
+            let start = instance_data.len() as u32;
+            let mut quads: Vec<&Quad> = quads.iter().collect();
+            quads.sort_by(|a, b| quad_distance(b).total_cmp(&quad_distance(a)));
+            instance_data.extend(quads.into_iter().map(|quad| quad.to_raw()));
+            self.sorted.push((*pos, *bounds, start..instance_data.len() as u32));
+        }
+
+        if !instance_data.is_empty() {
+            graphics.queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&instance_data));
+        }
+    }
+
+    /**
+     * Picks out the sections to draw, keeping their order. Only takes effect once sorted.
+     */
+    pub fn cull(&mut self, visible: impl Fn(SectionPos, &Aabb) -> bool) -> CullingStats {
+        let mut stats = CullingStats {
+            meshes: self.sorted.len() as u32,
+            quads: self.num_quads,
+            ..Default::default()
+        };
+        self.visible.clear();
+        for (_, _, range) in self.sorted.iter().filter(|(pos, bounds, _)| visible(*pos, bounds)) {
+            stats.visible_meshes += 1;
+            stats.visible_quads += range.len() as u32;
+            match self.visible.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => self.visible.push(range.clone()),
+            }
+        }
+        stats
+    }
+
+    /**
+     * Draws the quads of the sections not culled, with everything but the instances already set.
+     */
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, num_indices: u32) {
+        render_pass.set_vertex_buffer(1, self.buffer.slice(..));
+        for range in &self.visible {
+            render_pass.draw_indexed(0..num_indices, 0, range.clone());
+        }
+    }
+}
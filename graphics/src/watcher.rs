@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+// How often the files are looked at again, since walking them each frame would be wasteful
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/**
+ * Notices when files change, appear or disappear under a set of files and directories, by
+ * comparing their modification times every so often.
+ */
+pub(crate) struct FileWatcher {
+    paths: Vec<PathBuf>,
+    stamps: BTreeMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let stamps = stamps(&paths);
+        log::info!("Watching {} files for changes", stamps.len());
+        Self {
+            paths,
+            stamps,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /**
+     * Whether anything changed since the last time this returned true.
+     */
+    pub fn changed(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let stamps = stamps(&self.paths);
+        if stamps == self.stamps {
+            return false;
+        }
+        self.stamps = stamps;
+        true
+    }
+}
+
+fn stamps(paths: &[PathBuf]) -> BTreeMap<PathBuf, SystemTime> {
+    let mut stamps = BTreeMap::new();
+    for path in paths {
+        add_stamps(path, &mut stamps);
+    }
+    stamps
+}
+
+// Files that can't be read right now, such as ones half way through being saved, are left out
+// and picked up on a later poll
+fn add_stamps(path: &Path, stamps: &mut BTreeMap<PathBuf, SystemTime>) {
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path).into_iter().flatten().flatten() {
+            add_stamps(&entry.path(), stamps);
+        }
+    } else if let Ok(modified) = path.metadata().and_then(|metadata| metadata.modified()) {
+        stamps.insert(path.to_path_buf(), modified);
+    }
+}
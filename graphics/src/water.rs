@@ -0,0 +1,138 @@
+use wgpu::{BindGroup, BindGroupLayout, Buffer};
+use wgpu::util::DeviceExt;
+
+use world::clock::{TICKS_PER_SECOND, WorldClock};
+use world::material::RenderLayer;
+
+use crate::graphics::Graphics;
+use crate::pipeline::Pipeline;
+use crate::texture::Texture;
+use crate::translucent::TranslucentQuads;
+use crate::Vertex;
+
+// The wave time wraps around after this many seconds, before it loses precision
+const TIME_PERIOD: f64 = 3600.;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct WaterUniform {
+    // Seconds of world time in x
+    params: [f32; 4],
+}
+
+/**
+ * Water surfaces, drawn by their own pipeline after the rest of the world so they can tint by how
+ * much water lies between them and the scene behind. The depth buffer is read while drawing, so
+ * the pass must attach it read-only.
+ */
+pub struct Water {
+    pub quads: TranslucentQuads,
+    buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    pipeline: Pipeline,
+}
+
+impl Water {
+    pub fn new(graphics: &Graphics, quads: TranslucentQuads, depth_texture: &Texture, camera_bind_group_layout: &BindGroupLayout, sky_bind_group_layout: &BindGroupLayout, shader: &str) -> Self {
+        let buffer = graphics.device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Water Buffer"),
+                contents: bytemuck::cast_slice(&[WaterUniform { params: [0.; 4] }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let bind_group_layout = graphics.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("water_bind_group_layout"),
+        });
+
+        let bind_group = Self::create_bind_group(graphics, &bind_group_layout, &buffer, depth_texture);
+
+        let pipeline_layout = graphics.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Water Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, sky_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = Pipeline::new(
+            graphics,
+            "Water",
+            "Water",
+            shader,
+            Vertex::init_buffer_layout(),
+            Some(&pipeline_layout),
+            RenderLayer::Translucent,
+        );
+
+        Self {
+            quads,
+            buffer,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    fn create_bind_group(graphics: &Graphics, layout: &BindGroupLayout, buffer: &Buffer, depth_texture: &Texture) -> BindGroup {
+        graphics.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                },
+            ],
+            label: Some("water_bind_group"),
+        })
+    }
+
+    /**
+     * Rebinds the depth buffer after it was recreated for a new size.
+     */
+    pub fn resize(&mut self, graphics: &Graphics, depth_texture: &Texture) {
+        self.bind_group = Self::create_bind_group(graphics, &self.bind_group_layout, &self.buffer, depth_texture);
+    }
+
+    pub fn update(&self, graphics: &Graphics, clock: &WorldClock) {
+        let seconds = (clock.ticks() / TICKS_PER_SECOND % TIME_PERIOD) as f32;
+        graphics.queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[WaterUniform { params: [seconds, 0., 0., 0.] }]));
+    }
+
+    /**
+     * Draws the water with the quad vertex and index buffers already set.
+     */
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a BindGroup, sky_bind_group: &'a BindGroup, num_indices: u32) {
+        render_pass.set_pipeline(&self.pipeline.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, sky_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.bind_group, &[]);
+        self.quads.draw(render_pass, num_indices);
+    }
+}
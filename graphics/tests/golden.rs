@@ -0,0 +1,247 @@
+// Renders fixed scenes offscreen and compares them against the golden images in `tests/golden`.
+//
+// Run with `UPDATE_GOLDEN=1` to write the current renders as the new golden images, after
+// checking that a rendering change looks as intended. When a scene no longer matches, the render
+// and an image marking the differing pixels are written to the cargo target directory.
+//
+// Scenes are rendered on the software adapter, so the golden images don't depend on the GPU. A
+// machine without one fails these tests rather than passing them unchecked.
+
+use std::path::{Path, PathBuf};
+
+use image::{Rgba, RgbaImage};
+
+use graphics::CameraPose;
+use graphics::resource_pack::DEFAULT_RESOURCE_PACK;
+use graphics::settings::{FogMode, Settings, Tonemapper};
+use math::point::{Coord2DI, Coord3DI};
+use world::chunk::{Chunk, ChunkGenerator, VanillaGenerator};
+use world::clock::{MIDNIGHT, NOON, SUNSET};
+use world::material::{GLASS, GLOWSTONE, OAK_LEAVES, STONE, WATER};
+use world::world::World;
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 180;
+
+// Largest colour difference two pixels can have and still count as the same, from 0 to 1
+const PIXEL_THRESHOLD: f64 = 0.1;
+// Share of pixels allowed to differ, for small differences between drivers
+const MAX_DIFFERING: f64 = 0.005;
+
+/**
+ * A fixed view of a generated world.
+ */
+struct Scene {
+    name: &'static str,
+    seed: u32,
+    // Chunks generated around the origin
+    radius: i32,
+    time_of_day: u64,
+    camera: CameraPose,
+    settings: Settings,
+    // Changes to the origin chunk before it is lit
+    build: Option<fn(&mut Chunk)>,
+}
+
+impl Scene {
+    fn world(&self) -> World {
+        let generator = VanillaGenerator::new(self.seed);
+        let mut world = World::new();
+        for x in -self.radius..=self.radius {
+            for z in -self.radius..=self.radius {
+                let pos = Coord2DI::new(x, z);
+                let mut chunk = generator.generate_chunk(pos);
+                if let (Some(build), true) = (self.build, x == 0 && z == 0) {
+                    build(&mut chunk);
+                }
+                world.insert_chunk(chunk);
+            }
+        }
+        world.clock.set_time_of_day(self.time_of_day);
+        world
+    }
+}
+
+/**
+ * A stone floor near the top of the chunk with the terrain above it cleared, holding a pool of
+ * water, a glass pillar, a glowstone block and some leaves.
+ */
+fn showcase(chunk: &mut Chunk) {
+    const FLOOR: i32 = 240;
+    for x in 0..16 {
+        for z in 0..16 {
+            for y in FLOOR + 1..256 {
+                chunk.set_block(Coord3DI::new(x, y, z), None);
+            }
+            chunk.set_block(Coord3DI::new(x, FLOOR, z), Some(STONE));
+            chunk.set_block(Coord3DI::new(x, FLOOR - 1, z), Some(STONE));
+        }
+    }
+    for x in 2..7 {
+        for z in 2..7 {
+            chunk.set_block(Coord3DI::new(x, FLOOR, z), Some(WATER));
+        }
+    }
+    for y in FLOOR + 1..FLOOR + 4 {
+        chunk.set_block(Coord3DI::new(11, y, 4), Some(GLASS));
+    }
+    chunk.set_block(Coord3DI::new(4, FLOOR + 1, 11), Some(GLOWSTONE));
+    for x in 9..12 {
+        for z in 9..12 {
+            chunk.set_block(Coord3DI::new(x, FLOOR + 1, z), Some(OAK_LEAVES));
+        }
+    }
+    chunk.set_block(Coord3DI::new(10, FLOOR + 2, 10), Some(OAK_LEAVES));
+}
+
+// The settings scenes start from, with textures from the repository's resource pack
+fn default_settings() -> Settings {
+    Settings {
+        resource_packs: vec![Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(DEFAULT_RESOURCE_PACK)],
+        ..Settings::default()
+    }
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{}.png", name))
+}
+
+fn output_path(name: &str, kind: &str) -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden").join(format!("{}-{}.png", name, kind))
+}
+
+// Brightness and the two colour axes of YIQ, which are weighed by how much the eye notices them
+fn yiq(pixel: &Rgba<u8>) -> [f64; 3] {
+    let [r, g, b, _] = pixel.0.map(|c| c as f64);
+    [
+        r * 0.29889531 + g * 0.58662247 + b * 0.11448223,
+        r * 0.59597799 - g * 0.27417610 - b * 0.32180189,
+        r * 0.21147017 - g * 0.52261711 + b * 0.31114694,
+    ]
+}
+
+/**
+ * Perceptual difference between two pixels, from 0 for equal ones to 1 for black against white.
+ */
+fn pixel_difference(a: &Rgba<u8>, b: &Rgba<u8>) -> f64 {
+    // The largest weighted difference, between black and white
+    const MAX_DELTA: f64 = 35215.;
+    let ([ya, ia, qa], [yb, ib, qb]) = (yiq(a), yiq(b));
+    let delta = 0.5053 * (ya - yb).powi(2) + 0.299 * (ia - ib).powi(2) + 0.1957 * (qa - qb).powi(2);
+    (delta / MAX_DELTA).sqrt()
+}
+
+/**
+ * Counts the pixels that differ noticeably, and marks them in red over a faded copy of the golden
+ * image.
+ */
+fn compare(actual: &RgbaImage, golden: &RgbaImage) -> (usize, RgbaImage) {
+    let mut differing = 0;
+    let diff = RgbaImage::from_fn(golden.width(), golden.height(), |x, y| {
+        let (a, g) = (actual.get_pixel(x, y), golden.get_pixel(x, y));
+        if pixel_difference(a, g) > PIXEL_THRESHOLD {
+            differing += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [y, _, _] = yiq(g);
+            let faded = (255. - (255. - y) * 0.2) as u8;
+            Rgba([faded, faded, faded, 255])
+        }
+    });
+    (differing, diff)
+}
+
+fn check(scene: Scene) {
+    let world = scene.world();
+    let actual = pollster::block_on(graphics::render_image(&world, scene.settings, WIDTH, HEIGHT, scene.camera, true))
+        .unwrap_or_else(|e| panic!("Failed to render {}: {}", scene.name, e));
+
+    let golden_path = golden_path(scene.name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(golden_path.parent().unwrap()).unwrap();
+        actual.save(&golden_path).unwrap();
+        return;
+    }
+
+    let golden = image::open(&golden_path)
+        .unwrap_or_else(|e| panic!("No golden image at {}, run with UPDATE_GOLDEN=1 to create it: {}", golden_path.display(), e))
+        .to_rgba8();
+    assert_eq!(actual.dimensions(), golden.dimensions(), "{} was rendered at another size than its golden image", scene.name);
+
+    let (differing, diff) = compare(&actual, &golden);
+    let share = differing as f64 / (WIDTH * HEIGHT) as f64;
+    if share > MAX_DIFFERING {
+        let (actual_path, diff_path) = (output_path(scene.name, "actual"), output_path(scene.name, "diff"));
+        std::fs::create_dir_all(actual_path.parent().unwrap()).unwrap();
+        actual.save(&actual_path).unwrap();
+        diff.save(&diff_path).unwrap();
+        panic!(
+            "{} differs from its golden image in {:.2}% of pixels, see {} and {}",
+            scene.name,
+            share * 100.,
+            actual_path.display(),
+            diff_path.display(),
+        );
+    }
+}
+
+#[test]
+fn terrain_at_noon() {
+    check(Scene {
+        name: "terrain_at_noon",
+        seed: 42,
+        radius: 1,
+        time_of_day: NOON,
+        camera: CameraPose { position: [8., 280., 40.], yaw: 270., pitch: -40. },
+        settings: default_settings(),
+        build: None,
+    });
+}
+
+#[test]
+fn terrain_at_sunset() {
+    check(Scene {
+        name: "terrain_at_sunset",
+        seed: 42,
+        radius: 1,
+        time_of_day: SUNSET,
+        camera: CameraPose { position: [-45., 270., -45.], yaw: 45., pitch: -25. },
+        settings: Settings {
+            fog: FogMode::Exponential,
+            tonemapper: Tonemapper::Agx,
+            vignette: true,
+            ..default_settings()
+        },
+        build: None,
+    });
+}
+
+#[test]
+fn materials_at_noon() {
+    check(Scene {
+        name: "materials_at_noon",
+        seed: 7,
+        radius: 0,
+        time_of_day: NOON,
+        camera: CameraPose { position: [8., 248., 22.], yaw: 270., pitch: -30. },
+        settings: default_settings(),
+        build: Some(showcase),
+    });
+}
+
+#[test]
+fn materials_at_midnight() {
+    check(Scene {
+        name: "materials_at_midnight",
+        seed: 7,
+        radius: 0,
+        time_of_day: MIDNIGHT,
+        camera: CameraPose { position: [8., 248., 22.], yaw: 270., pitch: -30. },
+        settings: Settings {
+            shadows: false,
+            fxaa: false,
+            ..default_settings()
+        },
+        build: Some(showcase),
+    });
+}
@@ -41,6 +41,10 @@ impl BlockMap {
         self.vectors.iter()
     }
 
+    pub fn into_vectors(self) -> Vec<BlockVector> {
+        self.vectors
+    }
+
     pub fn quads(&self) -> Vec<&Quad> {
         self.vectors.iter().flat_map(|vector| vector.get_faces().iter()).collect::<Vec<&Quad>>()
     }
@@ -100,4 +100,8 @@ impl BlockVector {
     pub fn get_faces(&self) -> &Vec<Quad> {
         &self.faces
     }
+
+    pub fn get_faces_mut(&mut self) -> &mut Vec<Quad> {
+        &mut self.faces
+    }
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
 use std::any::TypeId;
 use std::fmt::{Debug, Display};
+use std::hash::{Hash, Hasher};
 use num_traits::{FromPrimitive, Num, NumCast, ToPrimitive};
 
 pub trait Point<T>: Num + NumCast + ToPrimitive + FromPrimitive + Clone + Debug + Display {}
@@ -35,13 +36,13 @@ fn try_cast<T: Point<T>, A: Point<A> + 'static>(x: T) -> Option<A> {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Point2<T: Point<T>> {
     pub x: T,
     pub y: T,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Point3<T: Point<T>> {
     pub x: T,
     pub y: T,
@@ -67,6 +68,25 @@ impl<T: Point<T>> Point3<T> {
     }
 }
 
+impl Eq for Point2<i32> {}
+
+impl Hash for Point2<i32> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.x.hash(state);
+        self.y.hash(state);
+    }
+}
+
+impl Eq for Point3<i32> {}
+
+impl Hash for Point3<i32> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.x.hash(state);
+        self.y.hash(state);
+        self.z.hash(state);
+    }
+}
+
 impl<T: Point<T>> Indexable for Point3<T> {
     fn index(&self) -> usize {
         index(&self.x, &self.y, &self.z)
@@ -82,7 +102,7 @@ impl<'a, A: Point<A> + 'static, T: Point<T> > LossyCast<Point2<A>> for Point2<T>
     }
 }
 
-impl<A: Point<A> + From<T>, T: Point<T>> LossyCast<Point3<A>> for Point3<T> {
+impl<A: Point<A> + From<T> + 'static, T: Point<T>> LossyCast<Point3<A>> for Point3<T> {
     fn cast(&self) -> Point3<A> {
         Point3 {
             x: try_cast(self.x.clone()).expect("Failed to cast x"),
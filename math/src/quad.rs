@@ -1,6 +1,6 @@
-use nalgebra::Rotation3;
+use nalgebra::{Rotation3, Vector3};
 
-use crate::point::Point3DF;
+use crate::point::{Point3DF, Point3DI};
 use crate::face::Face;
 
 #[derive(Clone, Debug)]
@@ -9,9 +9,27 @@ pub struct Quad {
     pub pos: Point3DF,
     pub rot: Rotation3<f32>,
     pub texture_index: u32,
+    // Sky light << 4 | block light at each corner, ordered like `CORNERS`
+    pub light: [u8; 4],
+    // Ambient occlusion at each corner, from 0 (fully occluded) to 3 (open)
+    pub ao: [u8; 4],
+    // Triangulated along the top right to bottom left diagonal instead of top left to bottom right
+    pub flipped: bool,
+    // For fluids, the direction the surface flows in along x and z
+    pub flow: [i8; 2],
+    // For fluids, the height of the surface in the block, `FULL_HEIGHT` when covered by more fluid
+    pub surface_height: u8,
+    // Light level the block gives off, from 0 to 15, which makes it glow
+    pub emission: u8,
 }
 
 impl Quad {
+    pub const FULL_LIGHT: u8 = 0xF0;
+    pub const NO_OCCLUSION: u8 = 3;
+    pub const FULL_HEIGHT: u8 = 255;
+    // Top left, top right, bottom left and bottom right of the unrotated quad
+    pub const CORNERS: [[f32; 2]; 4] = [[-0.5, 0.5], [0.5, 0.5], [-0.5, -0.5], [0.5, -0.5]];
+
     pub fn new(position: Point3DF, facing: Face, texture_index: u32) -> Self {
         let rotation: Rotation3<f32>;
         match facing {
@@ -44,9 +62,48 @@ impl Quad {
             pos: position,
             rot: rotation,
             texture_index,
+            light: [Self::FULL_LIGHT; 4],
+            ao: [Self::NO_OCCLUSION; 4],
+            flipped: false,
+            flow: [0; 2],
+            surface_height: Self::FULL_HEIGHT,
+            emission: 0,
         }
     }
 
+    /**
+     * The two unit steps along the face, in world space, that lead from its centre towards each corner.
+     */
+    pub fn corner_directions(&self) -> [([i32; 3], [i32; 3]); 4] {
+        let step = |v: Vector3<f32>| {
+            let v = self.rot * v;
+            [v.x.round() as i32, v.y.round() as i32, v.z.round() as i32]
+        };
+        Self::CORNERS.map(|[x, y]| (step(Vector3::new(x.signum(), 0., 0.)), step(Vector3::new(0., y.signum(), 0.))))
+    }
+
+    /**
+     * The block this is a face of, half a block behind it.
+     */
+    pub fn block(&self) -> Point3DI {
+        let normal = self.rot * Vector3::new(0., 0., 0.5);
+        Point3DI::new(
+            (self.pos.x - normal.x).round() as i32,
+            (self.pos.y - normal.y).round() as i32,
+            (self.pos.z - normal.z).round() as i32,
+        )
+    }
+
+    /**
+     * Sets per corner light and occlusion, flipping the quad when that keeps the darker corners
+     * off the shared diagonal so occlusion interpolates evenly.
+     */
+    pub fn set_corners(&mut self, light: [u8; 4], ao: [u8; 4]) {
+        self.light = light;
+        self.ao = ao;
+        self.flipped = ao[0] + ao[3] < ao[1] + ao[2];
+    }
+
     pub fn set_texture_index(&mut self, texture_index: u32) {
         self.texture_index = texture_index;
     }
@@ -1,14 +1,15 @@
 use math::block::block_vector::BlockVector;
 use math::point::Coord3DI;
-use crate::material::Material;
+use crate::material::{Material, TextureLayers};
 
+#[derive(Clone, Copy, Debug)]
 pub struct Block {
     pub pos: Coord3DI,
     pub material: Material,
 }
 
 impl Block {
-    pub fn to_vector(&self, faces: [bool; 6]) -> BlockVector {
-        self.material.to_vector(self.pos, faces)
+    pub fn to_vector(&self, faces: [bool; 6], layers: &TextureLayers) -> BlockVector {
+        self.material.to_vector(self.pos, faces, layers)
     }
 }
\ No newline at end of file
@@ -1,22 +1,159 @@
+use std::cell::Cell;
+
 use log::error;
 use noise::{NoiseFn, Perlin};
 
 use math::{CHUNK_HEIGHT, CHUNK_SIZE};
-use math::block::block_vector::BlockVector;
-use math::point::{Coord2DI, Coord3DI, index};
+use math::point::{Coord2DI, Coord3DI, Indexable, index};
 
 use crate::block::Block;
-use crate::material::{DIRT, GRASS};
+use crate::light::{LightChannel, MAX_LIGHT};
+use crate::material::{DIRT, GRASS, Material};
+use crate::visibility::{SECTION_SIZE, SECTIONS, SectionVisibility};
 
 pub struct Chunk {
     pub pos: Coord2DI,
-    pub blocks: Vec<Block>,
+    blocks: Vec<Block>,
+    // Maps a flattened local position to its index in `blocks` plus one, 0 being air
+    lookup: Vec<u32>,
+    // Sky light in the high nibble and block light in the low nibble, indexed like `lookup`
+    light: Vec<u8>,
+    // How each section connects its faces, and which sections changed since it was worked out.
+    // Worked out when first asked for, so edits in a row don't each pay for it
+    visibility: [Cell<SectionVisibility>; SECTIONS],
+    stale_sections: [Cell<bool>; SECTIONS],
 }
 
 impl Chunk {
+    pub fn new(pos: Coord2DI) -> Self {
+        Self {
+            pos,
+            blocks: Vec::new(),
+            lookup: vec![0; CHUNK_SIZE * CHUNK_SIZE * CHUNK_HEIGHT],
+            light: vec![0; CHUNK_SIZE * CHUNK_SIZE * CHUNK_HEIGHT],
+            visibility: std::array::from_fn(|_| Cell::new(SectionVisibility::ALL)),
+            stale_sections: std::array::from_fn(|_| Cell::new(true)),
+        }
+    }
+
+    pub fn in_bounds(x: i32, y: i32, z: i32) -> bool {
+        (0..CHUNK_SIZE as i32).contains(&x)
+            && (0..CHUNK_HEIGHT as i32).contains(&y)
+            && (0..CHUNK_SIZE as i32).contains(&z)
+    }
+
     pub fn get_block(&self, index: usize) -> Option<&Block> {
         self.blocks.get(index)
     }
+
+    pub fn blocks(&self) -> &Vec<Block> {
+        &self.blocks
+    }
+
+    /**
+     * Returns the block at a position local to this chunk, or `None` for air and out of bounds positions.
+     */
+    pub fn block_at(&self, x: i32, y: i32, z: i32) -> Option<&Block> {
+        if !Self::in_bounds(x, y, z) {
+            return None;
+        }
+
+        match self.lookup[index(&x, &y, &z)] {
+            0 => None,
+            i => self.blocks.get(i as usize - 1),
+        }
+    }
+
+    /**
+     * Approximate number of bytes this chunk occupies in memory.
+     */
+    pub fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.blocks.capacity() * std::mem::size_of::<Block>()
+            + self.lookup.capacity() * std::mem::size_of::<u32>()
+            + self.light.capacity()
+    }
+
+    /**
+     * Returns both light channels packed as sky light << 4 | block light, 0 for out of bounds positions.
+     */
+    pub fn packed_light(&self, x: i32, y: i32, z: i32) -> u8 {
+        if !Self::in_bounds(x, y, z) {
+            return 0;
+        }
+        self.light[index(&x, &y, &z)]
+    }
+
+    pub fn get_light(&self, channel: LightChannel, x: i32, y: i32, z: i32) -> u8 {
+        let packed = self.packed_light(x, y, z);
+        match channel {
+            LightChannel::Sky => packed >> 4,
+            LightChannel::Block => packed & 0xF,
+        }
+    }
+
+    pub fn set_light(&mut self, channel: LightChannel, x: i32, y: i32, z: i32, level: u8) {
+        if !Self::in_bounds(x, y, z) {
+            return;
+        }
+
+        let packed = &mut self.light[index(&x, &y, &z)];
+        let level = level.min(MAX_LIGHT);
+        *packed = match channel {
+            LightChannel::Sky => (*packed & 0x0F) | (level << 4),
+            LightChannel::Block => (*packed & 0xF0) | level,
+        };
+    }
+
+    /**
+     * Returns the highest block in a column, `None` if the column is empty.
+     */
+    pub fn top_block(&self, x: i32, z: i32) -> Option<&Block> {
+        (0..CHUNK_HEIGHT as i32).rev().find_map(|y| self.block_at(x, y, z))
+    }
+
+    /**
+     * How a section connects its faces, worked out again if it changed since the last time.
+     */
+    pub fn section_visibility(&self, section: usize) -> SectionVisibility {
+        if self.stale_sections[section].replace(false) {
+            self.visibility[section].set(SectionVisibility::compute(self, section));
+        }
+        self.visibility[section].get()
+    }
+
+    /**
+     * Places (or replaces) a block at its local position, `None` clears it to air.
+     * Out of bounds positions are ignored. Lighting is left untouched, see `World::set_block`.
+     */
+    pub fn set_block(&mut self, pos: Coord3DI, material: Option<Material>) {
+        if !Self::in_bounds(pos.x, pos.y, pos.z) {
+            return;
+        }
+        self.stale_sections[pos.y as usize / SECTION_SIZE].set(true);
+
+        let i = pos.index();
+        match (self.lookup[i], material) {
+            (0, Some(material)) => {
+                self.blocks.push(Block { pos, material });
+                self.lookup[i] = self.blocks.len() as u32;
+            }
+            (existing, Some(material)) => {
+                self.blocks[existing as usize - 1].material = material;
+            }
+            (0, None) => {}
+            (existing, None) => {
+                let removed = existing as usize - 1;
+                self.blocks.swap_remove(removed);
+                self.lookup[i] = 0;
+
+                // The last block was moved into the removed slot
+                if let Some(moved) = self.blocks.get(removed) {
+                    self.lookup[moved.pos.index()] = existing;
+                }
+            }
+        }
+    }
 }
 
 pub trait ChunkGenerator {
@@ -24,42 +161,49 @@ pub trait ChunkGenerator {
 }
 
 pub struct VanillaGenerator {
-    noise: Vec<f64>,
+    perlin: Perlin,
 }
 
 impl VanillaGenerator {
     pub fn new(seed: u32) -> Self {
-        let perlin = Perlin::new(seed);
-        let mut noise = Vec::new();
+        Self {
+            perlin: Perlin::new(seed)
+        }
+    }
 
-        for y in 0..CHUNK_HEIGHT {
+    fn sample_noise(&self, pos: Coord2DI) -> Vec<f64> {
+        // Sampled in world space so neighbouring chunks line up, one extra layer on top for the air check
+        let mut noise = Vec::with_capacity(CHUNK_SIZE * CHUNK_SIZE * (CHUNK_HEIGHT + 1));
+
+        for y in 0..=CHUNK_HEIGHT {
             for z in 0..CHUNK_SIZE {
                 for x in 0..CHUNK_SIZE {
-                    noise.push(perlin.get([x as f64 * 0.01, y as f64 * 0.01, z as f64 * 0.01]));
+                    let world_x = (pos.x * CHUNK_SIZE as i32) as f64 + x as f64;
+                    let world_z = (pos.y * CHUNK_SIZE as i32) as f64 + z as f64;
+                    noise.push(self.perlin.get([world_x * 0.01, y as f64 * 0.01, world_z * 0.01]));
                 }
             }
         }
 
-        Self {
-            noise
-        }
+        noise
     }
 
-    fn get_noise(&self, x: i32, y: i32, z: i32) -> &f64 {
+    fn get_noise(noise: &[f64], x: i32, y: i32, z: i32) -> &f64 {
         let i = index(&x, &y, &z);
 
-        if i >= self.noise.len() {
+        if i >= noise.len() {
             error!("Noise index out of bounds: {} {} {} -> {}", x, y, z, i);
             return &0.0;
         }
 
-        self.noise.get(i).unwrap()
+        noise.get(i).unwrap()
     }
 }
 
 impl ChunkGenerator for VanillaGenerator {
     fn generate_chunk(&self, pos: Coord2DI) -> Chunk {
-        let mut blocks = Vec::new();
+        let noise = self.sample_noise(pos);
+        let mut chunk = Chunk::new(pos);
         for y in 0..CHUNK_HEIGHT {
             for z in 0..CHUNK_SIZE {
                 for x in 0..CHUNK_SIZE {
@@ -69,34 +213,24 @@ impl ChunkGenerator for VanillaGenerator {
 
                     let pos = Coord3DI::new(xi, yi, zi);
 
-                    let noise = self.get_noise(xi, yi, zi);
+                    let noise_value = Self::get_noise(&noise, xi, yi, zi);
 
-                    if noise < &0.0 {
+                    if noise_value < &0.0 {
                         // Air
                         continue;
                     }
 
                     // Check if the block above is air
-                    if self.get_noise(xi, yi + 1, zi) < &0.0 {
+                    if Self::get_noise(&noise, xi, yi + 1, zi) < &0.0 {
                         // Grass block
-                        blocks.push(Block {
-                            pos,
-                            material: GRASS,
-                        });
+                        chunk.set_block(pos, Some(GRASS));
                     } else {
                         // Dirt block
-                        blocks.push(Block {
-                            pos,
-                            material: DIRT,
-                        });
+                        chunk.set_block(pos, Some(DIRT));
                     }
                 }
             }
         }
-        Chunk {
-            pos,
-            blocks,
-        }
+        chunk
     }
 }
-
@@ -0,0 +1,125 @@
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+// Length of a full day and night in ticks
+pub const DAY_LENGTH: u64 = 24000;
+pub const TICKS_PER_SECOND: f64 = 20.;
+
+// Times of day, in ticks since sunrise
+pub const SUNRISE: u64 = 0;
+pub const NOON: u64 = 6000;
+pub const SUNSET: u64 = 12000;
+pub const MIDNIGHT: u64 = 18000;
+
+// Sky light is never scaled below this, so nights are dark but not black
+const MIN_DAYLIGHT: f32 = 0.2;
+
+/**
+ * Keeps the time of a world in ticks, which decides where the sun and moon are and how bright
+ * the sky is.
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct WorldClock {
+    ticks: f64,
+    // How many times faster than real time the clock runs
+    pub speed: f64,
+}
+
+impl Default for WorldClock {
+    fn default() -> Self {
+        Self::new(NOON)
+    }
+}
+
+impl WorldClock {
+    pub fn new(time: u64) -> Self {
+        Self {
+            ticks: time as f64,
+            speed: 1.,
+        }
+    }
+
+    pub fn advance(&mut self, dt: Duration) {
+        self.ticks += dt.as_secs_f64() * TICKS_PER_SECOND * self.speed;
+    }
+
+    /**
+     * Ticks since the world was created.
+     */
+    pub fn time(&self) -> u64 {
+        self.ticks as u64
+    }
+
+    /**
+     * Ticks since the world was created, including how far into the current tick the clock is.
+     */
+    pub fn ticks(&self) -> f64 {
+        self.ticks
+    }
+
+    pub fn time_of_day(&self) -> u64 {
+        self.time() % DAY_LENGTH
+    }
+
+    pub fn day(&self) -> u64 {
+        self.time() / DAY_LENGTH
+    }
+
+    /**
+     * Moves the clock forward to the next time the day reaches `time_of_day`, so days are never
+     * lost by going back in time.
+     */
+    pub fn set_time_of_day(&mut self, time_of_day: u64) {
+        let time_of_day = time_of_day % DAY_LENGTH;
+        let mut day = self.day();
+        if time_of_day < self.time_of_day() {
+            day += 1;
+        }
+        self.ticks = (day * DAY_LENGTH + time_of_day) as f64;
+    }
+
+    /**
+     * Reads a time of day as either a number of ticks or one of sunrise, noon, sunset and midnight.
+     */
+    pub fn parse_time_of_day(value: &str) -> Option<u64> {
+        match value.to_lowercase().as_str() {
+            "sunrise" | "day" => Some(SUNRISE),
+            "noon" => Some(NOON),
+            "sunset" => Some(SUNSET),
+            "midnight" | "night" => Some(MIDNIGHT),
+            _ => value.parse().ok(),
+        }
+    }
+
+    /**
+     * How far through the day it is, from 0 at sunrise through 0.25 at noon to 0.75 at midnight.
+     */
+    pub fn day_fraction(&self) -> f32 {
+        (self.ticks % DAY_LENGTH as f64 / DAY_LENGTH as f64) as f32
+    }
+
+    /**
+     * Unit vector pointing towards the sun, which rises in the east (+x) and sets in the west.
+     * It is tilted slightly south so it never passes straight overhead.
+     */
+    pub fn sun_direction(&self) -> [f32; 3] {
+        let angle = self.day_fraction() * TAU;
+        let (x, y, z) = (angle.cos(), angle.sin(), 0.25);
+        let length = (x * x + y * y + z * z).sqrt();
+        [x / length, y / length, z / length]
+    }
+
+    pub fn moon_direction(&self) -> [f32; 3] {
+        self.sun_direction().map(|v| -v)
+    }
+
+    /**
+     * The factor sky light is scaled by, fading between full daylight and moonlight around
+     * sunrise and sunset.
+     */
+    pub fn daylight(&self) -> f32 {
+        let height = self.sun_direction()[1];
+        let t = ((height + 0.1) / 0.4).clamp(0., 1.);
+        MIN_DAYLIGHT + (1. - MIN_DAYLIGHT) * t * t * (3. - 2. * t)
+    }
+}
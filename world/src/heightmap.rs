@@ -0,0 +1,205 @@
+use std::error::Error;
+use std::path::Path;
+
+use image::{DynamicImage, ImageBuffer, Luma, RgbImage};
+
+use math::{CHUNK_HEIGHT, CHUNK_SIZE};
+use math::point::{Coord2DI, Coord3DI};
+
+use crate::chunk::{Chunk, ChunkGenerator};
+use crate::material::{DIRT, GRASS, Material, STONE};
+
+// Surface blocks keyed by the colour that selects them
+pub type ColorPalette = Vec<([u8; 3], Material)>;
+
+/**
+ * Builds terrain columns from a grayscale image, black being `min_height` and white `max_height`.
+ * Pixel (0, 0) lands on world (0, 0), columns outside the image are left empty.
+ */
+pub struct HeightmapGenerator {
+    heights: ImageBuffer<Luma<u16>, Vec<u16>>,
+    min_height: i32,
+    max_height: i32,
+    surface: Material,
+    soil: Material,
+    soil_depth: i32,
+    stone: Material,
+    colors: Option<(RgbImage, ColorPalette)>,
+}
+
+impl HeightmapGenerator {
+    /**
+     * Works with both 8 and 16 bit images, 16 bit ones keep their full precision.
+     */
+    pub fn new(heightmap: &DynamicImage, min_height: i32, max_height: i32) -> Self {
+        Self {
+            heights: heightmap.to_luma16(),
+            min_height: min_height.clamp(0, CHUNK_HEIGHT as i32 - 1),
+            max_height: max_height.clamp(0, CHUNK_HEIGHT as i32 - 1),
+            surface: GRASS,
+            soil: DIRT,
+            soil_depth: 3,
+            stone: STONE,
+            colors: None,
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>, min_height: i32, max_height: i32) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(&image::open(path)?, min_height, max_height))
+    }
+
+    /**
+     * Sets the top block, the `soil_depth` blocks below it and everything further down.
+     */
+    pub fn with_layers(mut self, surface: Material, soil: Material, soil_depth: i32, stone: Material) -> Self {
+        self.surface = surface;
+        self.soil = soil;
+        self.soil_depth = soil_depth.max(0);
+        self.stone = stone;
+        self
+    }
+
+    /**
+     * Picks each column's surface block from the palette entry closest to its colour in `image`.
+     * The image is stretched over the heightmap if their sizes differ.
+     */
+    pub fn with_color_map(mut self, image: &DynamicImage, palette: ColorPalette) -> Self {
+        if !palette.is_empty() {
+            self.colors = Some((image.to_rgb8(), palette));
+        }
+        self
+    }
+
+    fn height_at(&self, x: i32, z: i32) -> Option<i32> {
+        if x < 0 || z < 0 || x >= self.heights.width() as i32 || z >= self.heights.height() as i32 {
+            return None;
+        }
+
+        let value = self.heights.get_pixel(x as u32, z as u32).0[0] as f32 / u16::MAX as f32;
+        Some(self.min_height + (value * (self.max_height - self.min_height) as f32).round() as i32)
+    }
+
+    fn surface_at(&self, x: i32, z: i32) -> Material {
+        let (image, palette) = match &self.colors {
+            Some(colors) => colors,
+            None => return self.surface,
+        };
+
+        let (width, height) = self.heights.dimensions();
+        let cx = (x as u64 * image.width() as u64 / width as u64) as u32;
+        let cz = (z as u64 * image.height() as u64 / height as u64) as u32;
+        let color = image.get_pixel(cx, cz).0;
+
+        let distance = |target: &[u8; 3]| -> i32 {
+            (0..3).map(|i| (color[i] as i32 - target[i] as i32).pow(2)).sum()
+        };
+        palette.iter()
+            .min_by_key(|(target, _)| distance(target))
+            .map(|(_, material)| *material)
+            .unwrap_or(self.surface)
+    }
+}
+
+impl ChunkGenerator for HeightmapGenerator {
+    fn generate_chunk(&self, pos: Coord2DI) -> Chunk {
+        let mut chunk = Chunk::new(pos);
+
+        for z in 0..CHUNK_SIZE as i32 {
+            for x in 0..CHUNK_SIZE as i32 {
+                let world_x = pos.x * CHUNK_SIZE as i32 + x;
+                let world_z = pos.y * CHUNK_SIZE as i32 + z;
+
+                let height = match self.height_at(world_x, world_z) {
+                    Some(height) => height,
+                    None => continue,
+                };
+                let surface = self.surface_at(world_x, world_z);
+
+                for y in 0..=height {
+                    let material = if y == height {
+                        surface
+                    } else if y >= height - self.soil_depth {
+                        self.soil
+                    } else {
+                        self.stone
+                    };
+                    chunk.set_block(Coord3DI::new(x, y, z), Some(material));
+                }
+            }
+        }
+
+        chunk
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Rgb};
+
+    use crate::material::{GLASS, GLOWSTONE};
+
+    use super::*;
+
+    fn top(chunk: &Chunk, x: i32, z: i32) -> Option<(i32, Material)> {
+        chunk.top_block(x, z).map(|block| (block.pos.y, block.material))
+    }
+
+    #[test]
+    fn maps_8_bit_black_and_white_to_the_height_range() {
+        let image = GrayImage::from_raw(2, 1, vec![0, 255]).unwrap();
+        let chunk = HeightmapGenerator::new(&DynamicImage::ImageLuma8(image), 10, 50).generate_chunk(Coord2DI::new(0, 0));
+        assert_eq!(top(&chunk, 0, 0), Some((10, GRASS)));
+        assert_eq!(top(&chunk, 1, 0), Some((50, GRASS)));
+    }
+
+    #[test]
+    fn maps_16_bit_images_with_full_precision() {
+        let image = ImageBuffer::<Luma<u16>, _>::from_raw(3, 1, vec![0, u16::MAX, u16::MAX / 4]).unwrap();
+        let chunk = HeightmapGenerator::new(&DynamicImage::ImageLuma16(image), 0, 200).generate_chunk(Coord2DI::new(0, 0));
+        assert_eq!(top(&chunk, 0, 0).map(|(y, _)| y), Some(0));
+        assert_eq!(top(&chunk, 1, 0).map(|(y, _)| y), Some(200));
+        assert_eq!(top(&chunk, 2, 0).map(|(y, _)| y), Some(50));
+    }
+
+    #[test]
+    fn layers_surface_soil_and_stone() {
+        let image = GrayImage::from_raw(1, 1, vec![255]).unwrap();
+        let chunk = HeightmapGenerator::new(&DynamicImage::ImageLuma8(image), 0, 20)
+            .with_layers(GLASS, DIRT, 2, GLOWSTONE)
+            .generate_chunk(Coord2DI::new(0, 0));
+        let material = |y| chunk.block_at(0, y, 0).map(|block| block.material);
+        assert_eq!(material(21), None);
+        assert_eq!(material(20), Some(GLASS));
+        assert_eq!(material(19), Some(DIRT));
+        assert_eq!(material(18), Some(DIRT));
+        assert_eq!(material(17), Some(GLOWSTONE));
+        assert_eq!(material(0), Some(GLOWSTONE));
+    }
+
+    #[test]
+    fn picks_the_nearest_palette_colour_from_a_stretched_map() {
+        let heights = GrayImage::from_raw(4, 2, vec![128; 8]).unwrap();
+        // Half the size of the heightmap, so each colour covers two columns
+        let colors = RgbImage::from_fn(2, 1, |x, _| if x == 0 { Rgb([200, 40, 30]) } else { Rgb([20, 180, 60]) });
+        let palette = vec![([255, 0, 0], GLOWSTONE), ([0, 255, 0], GLASS), ([0, 0, 255], STONE)];
+        let chunk = HeightmapGenerator::new(&DynamicImage::ImageLuma8(heights), 0, 10)
+            .with_color_map(&DynamicImage::ImageRgb8(colors), palette)
+            .generate_chunk(Coord2DI::new(0, 0));
+
+        let surface = |x, z| top(&chunk, x, z).map(|(_, material)| material);
+        assert_eq!([surface(0, 0), surface(1, 1)], [Some(GLOWSTONE); 2]);
+        assert_eq!([surface(2, 0), surface(3, 1)], [Some(GLASS); 2]);
+    }
+
+    #[test]
+    fn leaves_columns_outside_the_image_empty() {
+        let image = GrayImage::from_raw(2, 3, vec![255; 6]).unwrap();
+        let generator = HeightmapGenerator::new(&DynamicImage::ImageLuma8(image), 0, 10);
+        let chunk = generator.generate_chunk(Coord2DI::new(0, 0));
+        assert!(top(&chunk, 1, 2).is_some());
+        assert_eq!(top(&chunk, 2, 0), None);
+        assert_eq!(top(&chunk, 0, 3), None);
+        assert!(generator.generate_chunk(Coord2DI::new(-1, 0)).blocks().is_empty());
+    }
+}
@@ -1,3 +1,13 @@
+
 pub mod chunk;
 pub mod block;
-pub mod material;
\ No newline at end of file
+pub mod material;
+pub mod world;
+pub mod nbt;
+pub mod schematic;
+pub mod heightmap;
+pub mod storage;
+pub mod light;
+pub mod mesher;
+pub mod clock;
+pub mod visibility;
@@ -0,0 +1,285 @@
+use std::collections::VecDeque;
+
+use math::{CHUNK_HEIGHT, CHUNK_SIZE};
+use math::face::Face;
+use math::point::{Coord2DI, Coord3DI};
+
+use crate::world::World;
+
+pub const MAX_LIGHT: u8 = 15;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightChannel {
+    // Light from the open sky, travels straight down without fading
+    Sky,
+    // Light emitted by blocks
+    Block,
+}
+
+pub const CHANNELS: [LightChannel; 2] = [LightChannel::Sky, LightChannel::Block];
+
+// Indexed like `Face`
+pub const NEIGHBOURS: [[i32; 3]; 6] = [
+    [0, 1, 0],
+    [0, -1, 0],
+    [-1, 0, 0],
+    [1, 0, 0],
+    [0, 0, 1],
+    [0, 0, -1]];
+
+pub fn neighbour(pos: Coord3DI, face: usize) -> Coord3DI {
+    let offset = NEIGHBOURS[face];
+    Coord3DI::new(pos.x + offset[0], pos.y + offset[1], pos.z + offset[2])
+}
+
+/**
+ * The level light has after travelling from a cell at `level` towards `face`.
+ */
+fn spread(channel: LightChannel, level: u8, face: usize) -> u8 {
+    if channel == LightChannel::Sky && face == Face::Down as usize && level == MAX_LIGHT {
+        MAX_LIGHT
+    } else {
+        level.saturating_sub(1)
+    }
+}
+
+/**
+ * Flood fills light outwards from every position in `queue`, raising darker neighbours.
+ */
+pub(crate) fn propagate(world: &mut World, channel: LightChannel, mut queue: VecDeque<Coord3DI>) {
+    while let Some(pos) = queue.pop_front() {
+        let level = world.get_light(pos, channel);
+        if level <= 1 {
+            continue;
+        }
+
+        for face in 0..NEIGHBOURS.len() {
+            let next = neighbour(pos, face);
+            if !world.can_hold_light(next) {
+                continue;
+            }
+
+            let next_level = spread(channel, level, face);
+            if world.get_light(next, channel) < next_level {
+                world.set_light(next, channel, next_level);
+                queue.push_back(next);
+            }
+        }
+    }
+}
+
+/**
+ * Darkens everything that was lit through the given (position, former level) pairs, then refills
+ * the darkened area from whatever light still borders it. The positions must already be set to 0.
+ */
+pub(crate) fn remove(world: &mut World, channel: LightChannel, mut queue: VecDeque<(Coord3DI, u8)>) {
+    let mut refill = VecDeque::new();
+
+    while let Some((pos, level)) = queue.pop_front() {
+        for face in 0..NEIGHBOURS.len() {
+            let next = neighbour(pos, face);
+            // Glowing blocks are sources of their own, opaque or not, seeded like in `light_chunk`
+            let emission = if channel == LightChannel::Block { world.light_emission(next) } else { 0 };
+            if emission > 0 {
+                if world.get_light(next, channel) < emission {
+                    world.set_light(next, channel, emission);
+                }
+                refill.push_back(next);
+                continue;
+            }
+            if !world.can_hold_light(next) {
+                continue;
+            }
+
+            let next_level = world.get_light(next, channel);
+            if next_level == 0 {
+                continue;
+            }
+
+            let fed_by_column = spread(channel, level, face) == MAX_LIGHT && next_level == MAX_LIGHT;
+            if next_level < level || fed_by_column {
+                // Lit through the removed light, so it goes dark too
+                world.set_light(next, channel, 0);
+                queue.push_back((next, next_level));
+            } else {
+                // Lit by another source which can fill the gap back in
+                refill.push_back(next);
+            }
+        }
+    }
+
+    propagate(world, channel, refill);
+}
+
+/**
+ * Lights a freshly inserted chunk: sky light down every open column, light flowing in from
+ * loaded neighbours, and its own light flowing out into them.
+ */
+pub(crate) fn light_chunk(world: &mut World, pos: Coord2DI) {
+    let size = CHUNK_SIZE as i32;
+    let mut sky = VecDeque::new();
+    let mut block = VecDeque::new();
+
+    for z in 0..size {
+        for x in 0..size {
+            for y in (0..CHUNK_HEIGHT as i32).rev() {
+                let cell = World::to_world(pos, Coord3DI::new(x, y, z));
+                if !world.can_hold_light(cell) {
+                    break;
+                }
+                world.set_light(cell, LightChannel::Sky, MAX_LIGHT);
+                sky.push_back(cell);
+            }
+        }
+    }
+
+    // Glowing blocks light up their surroundings
+    let emitters: Vec<(Coord3DI, u8)> = world.get_chunk(pos).map_or(vec![], |chunk| chunk.blocks().iter()
+        .filter(|block| block.material.light_emission() > 0)
+        .map(|block| (World::to_world(pos, block.pos), block.material.light_emission()))
+        .collect());
+    for (cell, emission) in emitters {
+        world.set_light(cell, LightChannel::Block, emission);
+        block.push_back(cell);
+    }
+
+    // Border cells of the neighbouring chunks, which may shine into this one
+    for z in -1..=size {
+        for x in -1..=size {
+            let on_side = (x == -1 || x == size) != (z == -1 || z == size);
+            if !on_side {
+                continue;
+            }
+            for y in 0..CHUNK_HEIGHT as i32 {
+                let cell = World::to_world(pos, Coord3DI::new(x, y, z));
+                if world.get_light(cell, LightChannel::Sky) > 1 {
+                    sky.push_back(cell);
+                }
+                if world.get_light(cell, LightChannel::Block) > 1 {
+                    block.push_back(cell);
+                }
+            }
+        }
+    }
+
+    propagate(world, LightChannel::Sky, sky);
+    propagate(world, LightChannel::Block, block);
+}
+
+/**
+ * Updates light after the block at `pos` changed, which may have opened or closed the space or
+ * changed how much light it gives off.
+ */
+pub(crate) fn update_block(world: &mut World, pos: Coord3DI, was_transparent: bool, was_emission: u8) {
+    let is_transparent = world.can_hold_light(pos);
+    if was_transparent != is_transparent {
+        update_opacity(world, pos, is_transparent);
+    }
+
+    let emission = world.light_emission(pos);
+    if emission != was_emission {
+        update_emission(world, pos, emission);
+    }
+}
+
+/**
+ * Updates light after any number of blocks changed, darkening what they lit before in one pass
+ * and then letting the light around them flow back in.
+ */
+pub(crate) fn update_blocks(world: &mut World, changed: &[Coord3DI]) {
+    for channel in CHANNELS {
+        let mut removed = VecDeque::new();
+        for &pos in changed {
+            let level = world.get_light(pos, channel);
+            world.set_light(pos, channel, 0);
+            if level > 0 {
+                removed.push_back((pos, level));
+            }
+        }
+        remove(world, channel, removed);
+
+        let mut queue = VecDeque::new();
+        for &pos in changed {
+            let emission = if channel == LightChannel::Block { world.light_emission(pos) } else { 0 };
+            if emission > world.get_light(pos, channel) {
+                world.set_light(pos, channel, emission);
+                queue.push_back(pos);
+            }
+            if world.can_hold_light(pos) {
+                queue.extend((0..NEIGHBOURS.len())
+                    .map(|face| neighbour(pos, face))
+                    .filter(|&next| world.get_light(next, channel) > 0));
+            }
+        }
+        propagate(world, channel, queue);
+    }
+}
+
+fn update_opacity(world: &mut World, pos: Coord3DI, is_transparent: bool) {
+    for channel in CHANNELS {
+        if is_transparent {
+            // Let the surrounding light flow into the opened space
+            let queue = (0..NEIGHBOURS.len())
+                .map(|face| neighbour(pos, face))
+                .filter(|&next| world.get_light(next, channel) > 0)
+                .collect();
+            propagate(world, channel, queue);
+        } else {
+            let level = world.get_light(pos, channel);
+            world.set_light(pos, channel, 0);
+            if level > 0 {
+                remove(world, channel, VecDeque::from([(pos, level)]));
+            }
+        }
+    }
+}
+
+/**
+ * Replaces the light given off at `pos` with `emission`, darkening whatever the old light reached
+ * before spreading the new one.
+ */
+fn update_emission(world: &mut World, pos: Coord3DI, emission: u8) {
+    let level = world.get_light(pos, LightChannel::Block);
+    world.set_light(pos, LightChannel::Block, 0);
+    if level > 0 {
+        remove(world, LightChannel::Block, VecDeque::from([(pos, level)]));
+    }
+
+    if emission > world.get_light(pos, LightChannel::Block) {
+        world.set_light(pos, LightChannel::Block, emission);
+        propagate(world, LightChannel::Block, VecDeque::from([pos]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::Chunk;
+    use crate::material::GLOWSTONE;
+
+    use super::*;
+
+    #[test]
+    fn removing_an_emitter_refills_from_the_one_next_to_it() {
+        // Diagonal neighbours, so the cells between them were lit by both
+        let (removed, kept) = (Coord3DI::new(4, 100, 4), Coord3DI::new(5, 101, 4));
+        let mut world = World::new();
+        world.set_block(removed, Some(GLOWSTONE));
+        world.set_block(kept, Some(GLOWSTONE));
+        world.set_block(removed, None);
+
+        let mut chunk = Chunk::new(Coord2DI::new(0, 0));
+        chunk.set_block(kept, Some(GLOWSTONE));
+        let mut fresh = World::new();
+        fresh.insert_chunk(chunk);
+
+        let reach = MAX_LIGHT as i32;
+        for y in kept.y - reach..=kept.y + reach {
+            for z in kept.z - reach..=kept.z + reach {
+                for x in kept.x - reach..=kept.x + reach {
+                    let pos = Coord3DI::new(x, y, z);
+                    assert_eq!(world.get_light(pos, LightChannel::Block), fresh.get_light(pos, LightChannel::Block), "at {:?}", pos);
+                }
+            }
+        }
+    }
+}
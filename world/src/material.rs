@@ -1,35 +1,245 @@
+use std::collections::HashMap;
+
 use math::block::block_vector::BlockVector;
 use math::point::Coord3DI;
 use math::face::Face;
 
+/**
+ * How a material is drawn, which also decides whether it hides the faces behind it.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderLayer {
+    Opaque,
+    // Either fully opaque or fully see-through per pixel, like leaves
+    Cutout,
+    // Blended with whatever is behind it, like glass and water
+    Translucent,
+}
+
+pub const MAX_FLUID_LEVEL: u8 = 7;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Material {
-    textures: [u32; 6],
+    name: &'static str,
+    // Block texture names, one per face in the order of `Face`
+    textures: [&'static str; 6],
+    fluid: bool,
+    // Block light level the material gives off
+    emission: u8,
+    layer: RenderLayer,
+    // How far a fluid has flowed from its source, from 0 for a full source block to 7
+    level: u8,
 }
 
 impl Material {
-    pub const fn new(textures: [u32; 6]) -> Self {
+    pub const fn new(name: &'static str, textures: [&'static str; 6]) -> Self {
+        Self {
+            name,
+            textures,
+            fluid: false,
+            emission: 0,
+            layer: RenderLayer::Opaque,
+            level: 0,
+        }
+    }
+
+    /**
+     * A material with the same texture on every face.
+     */
+    pub const fn cube(name: &'static str, texture: &'static str) -> Self {
+        Self::new(name, [texture; 6])
+    }
+
+    /**
+     * Marks the material as a fluid, which the camera can be submerged in.
+     */
+    pub const fn fluid(self) -> Self {
+        Self {
+            fluid: true,
+            ..self
+        }
+    }
+
+    /**
+     * Makes the material glow, lighting the blocks around it up to `level`.
+     */
+    pub const fn emissive(self, level: u8) -> Self {
         Self {
-            textures
+            emission: level,
+            ..self
+        }
+    }
+
+    pub const fn layer(self, layer: RenderLayer) -> Self {
+        Self {
+            layer,
+            ..self
+        }
+    }
+
+    /**
+     * The same fluid having flowed `level` blocks from its source. Levels past 7, which mark
+     * falling fluid, count as a full block.
+     */
+    pub const fn with_level(self, level: u8) -> Self {
+        Self {
+            level: if level > MAX_FLUID_LEVEL { 0 } else { level },
+            ..self
+        }
+    }
+
+    /**
+     * Looks up a registered material by its namespaced id. Block states other than a fluid's
+     * `level` are ignored (e.g. `minecraft:grass_block[snowy=false]`).
+     */
+    pub fn from_name(name: &str) -> Option<Material> {
+        let (id, state) = match name.split_once('[') {
+            Some((id, state)) => (id, state.trim_end_matches(']')),
+            None => (name, ""),
+        };
+        let material = MATERIALS.iter().find(|material| material.name == id).copied()?;
+
+        let level = state.split(',')
+            .filter_map(|property| property.trim().strip_prefix("level="))
+            .find_map(|level| level.parse().ok());
+        match level {
+            Some(level) if material.fluid => Some(material.with_level(level)),
+            _ => Some(material),
+        }
+    }
+
+    /**
+     * The namespaced id along with any block state that sets this material apart, as read by
+     * `from_name`.
+     */
+    pub fn block_state(&self) -> String {
+        if self.fluid && self.level > 0 {
+            format!("{}[level={}]", self.name, self.level)
+        } else {
+            self.name.to_string()
         }
     }
 
-    pub fn get_texture(&self, face: Face) -> u32 {
+    pub fn get_name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn is_fluid(&self) -> bool {
+        self.fluid
+    }
+
+    pub fn render_layer(&self) -> RenderLayer {
+        self.layer
+    }
+
+    /**
+     * Whether the material hides everything behind it and blocks light.
+     */
+    pub fn is_opaque(&self) -> bool {
+        self.layer == RenderLayer::Opaque
+    }
+
+    pub fn fluid_level(&self) -> u8 {
+        self.level
+    }
+
+    /**
+     * Whether both are the same block, whatever their state.
+     */
+    pub fn same_kind(&self, other: &Material) -> bool {
+        self.name == other.name
+    }
+
+    pub fn light_emission(&self) -> u8 {
+        self.emission
+    }
+
+    pub fn get_texture(&self, face: Face) -> &'static str {
         self.textures[face as usize]
     }
 
-    pub fn to_vector(&self, pos: Coord3DI, faces: [bool; 6]) -> BlockVector {
+    pub fn to_vector(&self, pos: Coord3DI, faces: [bool; 6], layers: &TextureLayers) -> BlockVector {
+        let texture = |face: Face| if faces[face as usize] { Some(layers.face_layer(self, face)) } else { None };
         let textures: [Option<u32>; 6] = [
-            if faces[Face::Up as usize] { Some(self.get_texture(Face::Up)) } else { None },
-            if faces[Face::Down as usize] { Some(self.get_texture(Face::Down)) } else { None },
-            if faces[Face::Left as usize] { Some(self.get_texture(Face::Left)) } else { None },
-            if faces[Face::Right as usize] { Some(self.get_texture(Face::Right)) } else { None },
-            if faces[Face::Front as usize] { Some(self.get_texture(Face::Front)) } else { None },
-            if faces[Face::Back as usize] { Some(self.get_texture(Face::Back)) } else { None },
+            texture(Face::Up),
+            texture(Face::Down),
+            texture(Face::Left),
+            texture(Face::Right),
+            texture(Face::Front),
+            texture(Face::Back),
         ];
 
         BlockVector::new(pos, textures)
     }
 }
 
-pub const GRASS: Material = Material::new([1, 2, 0, 0, 0, 0]);
-pub const DIRT: Material = Material::new([2, 2, 2, 2, 2, 2]);
\ No newline at end of file
+/**
+ * Where each block texture sits among the textures the renderer loaded, looked up by name. Names
+ * it doesn't know get the missing texture, so a block whose texture is absent still shows up.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct TextureLayers {
+    layers: HashMap<String, u32>,
+    // Textures replacing a block's own on some of its faces, by block id without the namespace
+    models: HashMap<String, [Option<String>; 6]>,
+}
+
+impl TextureLayers {
+    // The layer the renderer fills with a placeholder for absent textures
+    pub const MISSING: u32 = 0;
+
+    /**
+     * Maps each name to its position in `names`.
+     */
+    pub fn new<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+        let layers = names.into_iter()
+            .enumerate()
+            .map(|(layer, name)| (name.to_string(), layer as u32))
+            .collect();
+        Self { layers, models: HashMap::new() }
+    }
+
+    /**
+     * Gives the faces of `block` that have a texture in `textures` that texture instead of the
+     * material's own.
+     */
+    pub fn with_model(mut self, block: &str, textures: [Option<String>; 6]) -> Self {
+        self.models.insert(block.to_string(), textures);
+        self
+    }
+
+    pub fn layer(&self, name: &str) -> u32 {
+        self.layers.get(name).copied().unwrap_or(Self::MISSING)
+    }
+
+    /**
+     * The layer of the texture on one face of a material, going by its model if it has one.
+     */
+    pub fn face_layer(&self, material: &Material, face: Face) -> u32 {
+        let id = material.name.rsplit(':').next().unwrap_or(material.name);
+        let texture = self.models.get(id)
+            .and_then(|textures| textures[face as usize].as_deref())
+            .unwrap_or(material.get_texture(face));
+        self.layer(texture)
+    }
+}
+
+// Names follow the Minecraft ids so structures can be exchanged with existing voxel tooling
+// Textures are named after the files in a resource pack's `textures/block` directory
+pub const GRASS: Material = Material::new("minecraft:grass_block", [
+    "grass_block_top",
+    "dirt",
+    "grass_block_side",
+    "grass_block_side",
+    "grass_block_side",
+    "grass_block_side",
+]);
+pub const DIRT: Material = Material::cube("minecraft:dirt", "dirt");
+pub const STONE: Material = Material::cube("minecraft:stone", "stone");
+pub const GLOWSTONE: Material = Material::cube("minecraft:glowstone", "glowstone").emissive(15);
+pub const LAVA: Material = Material::cube("minecraft:lava", "lava").fluid().emissive(15);
+pub const OAK_LEAVES: Material = Material::cube("minecraft:oak_leaves", "oak_leaves").layer(RenderLayer::Cutout);
+pub const GLASS: Material = Material::cube("minecraft:glass", "glass").layer(RenderLayer::Translucent);
+pub const WATER: Material = Material::cube("minecraft:water", "water").layer(RenderLayer::Translucent).fluid();
+
+pub const MATERIALS: &[Material] = &[GRASS, DIRT, STONE, GLOWSTONE, LAVA, OAK_LEAVES, GLASS, WATER];
@@ -0,0 +1,261 @@
+use math::CHUNK_HEIGHT;
+use math::block::block_map::BlockMap;
+use math::face::Face;
+use math::point::{Coord2DI, Coord3DI};
+use math::quad::Quad;
+
+use crate::light::{neighbour, NEIGHBOURS};
+use crate::material::{Material, MAX_FLUID_LEVEL, RenderLayer, TextureLayers};
+use crate::world::World;
+
+/**
+ * The visible faces of a chunk, split by how they are drawn. Translucent fluids are kept apart
+ * from the rest of the translucent layer, as water has a pipeline of its own.
+ */
+pub struct ChunkMesh {
+    pub opaque: BlockMap,
+    pub cutout: BlockMap,
+    pub translucent: BlockMap,
+    pub fluid: BlockMap,
+}
+
+impl ChunkMesh {
+    fn new() -> Self {
+        Self {
+            opaque: BlockMap::new(),
+            cutout: BlockMap::new(),
+            translucent: BlockMap::new(),
+            fluid: BlockMap::new(),
+        }
+    }
+
+    pub fn layer(&self, layer: RenderLayer) -> &BlockMap {
+        match layer {
+            RenderLayer::Opaque => &self.opaque,
+            RenderLayer::Cutout => &self.cutout,
+            RenderLayer::Translucent => &self.translucent,
+        }
+    }
+
+    fn layer_mut(&mut self, layer: RenderLayer) -> &mut BlockMap {
+        match layer {
+            RenderLayer::Opaque => &mut self.opaque,
+            RenderLayer::Cutout => &mut self.cutout,
+            RenderLayer::Translucent => &mut self.translucent,
+        }
+    }
+}
+
+fn material_at(world: &World, pos: Coord3DI) -> Option<Material> {
+    world.get_block(pos).map(|block| block.material)
+}
+
+/**
+ * Whether `pos` is opaque, hiding the faces next to it and shading their corners. Unloaded chunks
+ * count as open so faces at the edge of the loaded world are still drawn, the underside of the
+ * world is always solid.
+ */
+fn is_solid(world: &World, pos: Coord3DI) -> bool {
+    if pos.y < 0 {
+        return true;
+    }
+    material_at(world, pos).is_some_and(|material| material.is_opaque())
+}
+
+/**
+ * Whether the face of a `material` block towards `pos` is hidden. Besides opaque blocks, faces
+ * between two blocks of the same translucent material are hidden so glass and water read as one
+ * volume. Cutout blocks like leaves keep them, as they can be seen through the holes.
+ */
+fn hides_face(world: &World, material: Material, pos: Coord3DI) -> bool {
+    if is_solid(world, pos) {
+        return true;
+    }
+    material.render_layer() == RenderLayer::Translucent && material_at(world, pos).is_some_and(|other| other.same_kind(&material))
+}
+
+/**
+ * The surface height of a fluid block and the direction it flows in, towards neighbours that have
+ * flowed further from the source or open space it can spread into. Fluid covered by more of the
+ * same fluid fills its block and stands still.
+ */
+fn fluid_surface(world: &World, material: Material, pos: Coord3DI) -> ([i8; 2], u8) {
+    let covered = material_at(world, neighbour(pos, Face::Up as usize)).is_some_and(|above| above.same_kind(&material));
+    if covered {
+        return ([0; 2], Quad::FULL_HEIGHT);
+    }
+
+    let level = material.fluid_level();
+    let mut flow = [0.; 2];
+    for face in [Face::Left, Face::Right, Face::Front, Face::Back] {
+        let direction = NEIGHBOURS[face as usize];
+        let next = neighbour(pos, face as usize);
+        let drop = match material_at(world, next) {
+            Some(other) if other.same_kind(&material) => other.fluid_level() as f32 - level as f32,
+            None if level > 0 => 1.,
+            _ => 0.,
+        };
+        flow[0] += direction[0] as f32 * drop;
+        flow[1] += direction[2] as f32 * drop;
+    }
+
+    let length = (flow[0] * flow[0] + flow[1] * flow[1]).sqrt();
+    let flow = if length > 0. { flow.map(|v| (v / length * 127.).round() as i8) } else { [0; 2] };
+    // A source fills 8/9 of its block, each level of flow lowers it by another ninth
+    let height = (MAX_FLUID_LEVEL + 1 - level) as f32 / 9.;
+    (flow, (height * Quad::FULL_HEIGHT as f32) as u8)
+}
+
+fn packed_light(world: &World, pos: Coord3DI) -> u8 {
+    if pos.y >= CHUNK_HEIGHT as i32 {
+        return Quad::FULL_LIGHT;
+    }
+
+    let (chunk, local) = World::to_local(pos);
+    world.get_chunk(chunk).map_or(Quad::FULL_LIGHT, |chunk| chunk.packed_light(local.x, local.y, local.z))
+}
+
+fn offset(pos: Coord3DI, step: [i32; 3]) -> Coord3DI {
+    Coord3DI::new(pos.x + step[0], pos.y + step[1], pos.z + step[2])
+}
+
+/**
+ * Smooth lights and occludes each corner of a face from the four cells in front of it that touch
+ * the corner: the one directly in front, the two beside it and the diagonal one.
+ */
+fn light_corners(world: &World, quad: &mut Quad, front: Coord3DI) {
+    let mut light = [0; 4];
+    let mut ao = [0; 4];
+
+    for (corner, (u, v)) in quad.corner_directions().into_iter().enumerate() {
+        let side_u = offset(front, u);
+        let side_v = offset(front, v);
+        let diagonal = offset(side_u, v);
+        let (solid_u, solid_v, solid_diagonal) = (is_solid(world, side_u), is_solid(world, side_v), is_solid(world, diagonal));
+
+        ao[corner] = if solid_u && solid_v {
+            0
+        } else {
+            Quad::NO_OCCLUSION - solid_u as u8 - solid_v as u8 - solid_diagonal as u8
+        };
+
+        // Solid cells are dark, so only open ones count towards the average. The diagonal is
+        // hidden when both sides are solid
+        let cells = [
+            Some(front),
+            (!solid_u).then_some(side_u),
+            (!solid_v).then_some(side_v),
+            (!solid_diagonal && !(solid_u && solid_v)).then_some(diagonal),
+        ];
+
+        let (mut sky, mut block, mut count) = (0, 0, 0);
+        for cell in cells.into_iter().flatten() {
+            let packed = packed_light(world, cell);
+            sky += (packed >> 4) as usize;
+            block += (packed & 0xF) as usize;
+            count += 1;
+        }
+        let average = |sum: usize| ((sum + count / 2) / count) as u8;
+        light[corner] = average(sky) << 4 | average(block);
+    }
+
+    quad.set_corners(light, ao);
+}
+
+/**
+ * Builds the visible faces of a chunk in world space, with smooth lighting and ambient occlusion.
+ * Faces get the texture layers `layers` gives their material's textures.
+ */
+pub fn mesh_chunk(world: &World, pos: Coord2DI, layers: &TextureLayers) -> ChunkMesh {
+    let mut mesh = ChunkMesh::new();
+    let chunk = match world.get_chunk(pos) {
+        Some(chunk) => chunk,
+        None => return mesh,
+    };
+
+    for block in chunk.blocks() {
+        let world_pos = World::to_world(pos, block.pos);
+        let faces: [bool; 6] = std::array::from_fn(|face| !hides_face(world, block.material, neighbour(world_pos, face)));
+        if !faces.contains(&true) {
+            continue;
+        }
+
+        let mut vector = block.material.to_vector(world_pos, faces, layers);
+        let emission = block.material.light_emission();
+        for quad in vector.get_faces_mut() {
+            light_corners(world, quad, neighbour(world_pos, quad.facing as usize));
+            // Glowing blocks are lit by their own light, whatever surrounds them
+            quad.light = quad.light.map(|packed| packed & 0xF0 | (packed & 0xF).max(emission));
+            quad.emission = emission;
+        }
+
+        if block.material.is_fluid() && block.material.render_layer() == RenderLayer::Translucent {
+            let (flow, surface_height) = fluid_surface(world, block.material, world_pos);
+            for quad in vector.get_faces_mut() {
+                quad.flow = flow;
+                quad.surface_height = surface_height;
+            }
+            mesh.fluid.push(vector);
+        } else {
+            mesh.layer_mut(block.material.render_layer()).push(vector);
+        }
+    }
+
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::material::{GLASS, STONE};
+
+    use super::*;
+
+    fn world_with(blocks: &[(i32, i32, i32, Material)]) -> World {
+        let mut world = World::new();
+        for &(x, y, z, material) in blocks {
+            world.set_block(Coord3DI::new(x, y, z), Some(material));
+        }
+        world
+    }
+
+    #[test]
+    fn occludes_corners_next_to_an_inside_corner() {
+        // A floor with walls along x = 0 and z = 0 standing on it
+        let mut blocks = vec![];
+        for x in 0..4 {
+            for z in 0..4 {
+                blocks.push((x, 10, z, STONE));
+            }
+            blocks.push((0, 11, x, STONE));
+            blocks.push((x, 11, 0, STONE));
+        }
+        let world = world_with(&blocks);
+        let mesh = mesh_chunk(&world, Coord2DI::new(0, 0), &TextureLayers::new([]));
+
+        let quad = mesh.opaque.quads().into_iter()
+            .find(|quad| quad.facing == Face::Up && quad.block() == Coord3DI::new(1, 10, 1))
+            .unwrap();
+        for (corner, (u, v)) in quad.corner_directions().into_iter().enumerate() {
+            let expected = match (u[0] + v[0], u[2] + v[2]) {
+                // Walled in on both sides
+                (-1, -1) => 0,
+                // One wall beside the corner plus the diagonal
+                (1, -1) | (-1, 1) => 1,
+                _ => Quad::NO_OCCLUSION,
+            };
+            assert_eq!(quad.ao[corner], expected, "corner towards {:?} {:?}", u, v);
+        }
+    }
+
+    #[test]
+    fn hides_faces_between_glass_but_not_towards_air() {
+        let world = world_with(&[(5, 20, 5, GLASS), (6, 20, 5, GLASS)]);
+        assert!(hides_face(&world, GLASS, Coord3DI::new(6, 20, 5)));
+        assert!(!hides_face(&world, GLASS, Coord3DI::new(4, 20, 5)));
+        assert!(!hides_face(&world, GLASS, Coord3DI::new(5, 21, 5)));
+
+        let mesh = mesh_chunk(&world, Coord2DI::new(0, 0), &TextureLayers::new([]));
+        assert_eq!(mesh.translucent.quad_len(), 10);
+        assert!(mesh.translucent.quads().iter().all(|quad| material_at(&world, neighbour(quad.block(), quad.facing as usize)).is_none()));
+    }
+}
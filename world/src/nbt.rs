@@ -0,0 +1,383 @@
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+// Guards against stack overflows from maliciously nested files
+const MAX_DEPTH: usize = 512;
+
+/**
+ * A single named binary tag, as used by Minecraft and most voxel tooling.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(BTreeMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    /**
+     * Reads any integer tag as an i64, widening smaller types.
+     */
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Tag::Byte(v) => Some(*v as i64),
+            Tag::Short(v) => Some(*v as i64),
+            Tag::Int(v) => Some(*v as i64),
+            Tag::Long(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Tag::Float(v) => Some(*v as f64),
+            Tag::Double(v) => Some(*v),
+            _ => self.as_i64().map(|v| v as f64),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&BTreeMap<String, Tag>> {
+        match self {
+            Tag::Compound(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&Vec<Tag>> {
+        match self {
+            Tag::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&Vec<u8>> {
+        match self {
+            Tag::ByteArray(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_int_array(&self) -> Option<&Vec<i32>> {
+        match self {
+            Tag::IntArray(ints) => Some(ints),
+            _ => None,
+        }
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn read_u8(reader: &mut impl Read) -> Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_i16(reader: &mut impl Read) -> Result<i16> {
+    let mut buf = [0; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(i16::from_be_bytes(buf))
+}
+
+fn read_i32(reader: &mut impl Read) -> Result<i32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(i32::from_be_bytes(buf))
+}
+
+fn read_i64(reader: &mut impl Read) -> Result<i64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(i64::from_be_bytes(buf))
+}
+
+fn read_len(reader: &mut impl Read) -> Result<usize> {
+    let len = read_i32(reader)?;
+    if len < 0 {
+        return Err(invalid(format!("Negative NBT length {}", len)));
+    }
+    Ok(len as usize)
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String> {
+    let len = read_i16(reader)? as u16 as usize;
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf)?;
+    // Java's modified UTF-8 only differs for NUL and supplementary characters, which are rare in ids
+    String::from_utf8(buf).map_err(|e| invalid(e.to_string()))
+}
+
+fn read_payload(reader: &mut impl Read, id: u8, depth: usize) -> Result<Tag> {
+    if depth > MAX_DEPTH {
+        return Err(invalid("NBT nested too deeply".to_string()));
+    }
+
+    Ok(match id {
+        1 => Tag::Byte(read_u8(reader)? as i8),
+        2 => Tag::Short(read_i16(reader)?),
+        3 => Tag::Int(read_i32(reader)?),
+        4 => Tag::Long(read_i64(reader)?),
+        5 => Tag::Float(f32::from_bits(read_i32(reader)? as u32)),
+        6 => Tag::Double(f64::from_bits(read_i64(reader)? as u64)),
+        7 => {
+            let len = read_len(reader)?;
+            let mut bytes = Vec::new();
+            reader.take(len as u64).read_to_end(&mut bytes)?;
+            if bytes.len() != len {
+                return Err(Error::from(ErrorKind::UnexpectedEof));
+            }
+            Tag::ByteArray(bytes)
+        }
+        8 => Tag::String(read_string(reader)?),
+        9 => {
+            let element = read_u8(reader)?;
+            let len = read_len(reader)?;
+            let mut list = Vec::new();
+            if element != 0 {
+                for _ in 0..len {
+                    list.push(read_payload(reader, element, depth + 1)?);
+                }
+            }
+            Tag::List(list)
+        }
+        10 => {
+            let mut map = BTreeMap::new();
+            loop {
+                let id = read_u8(reader)?;
+                if id == 0 {
+                    break;
+                }
+                let name = read_string(reader)?;
+                map.insert(name, read_payload(reader, id, depth + 1)?);
+            }
+            Tag::Compound(map)
+        }
+        11 => {
+            let len = read_len(reader)?;
+            let mut ints = Vec::new();
+            for _ in 0..len {
+                ints.push(read_i32(reader)?);
+            }
+            Tag::IntArray(ints)
+        }
+        12 => {
+            let len = read_len(reader)?;
+            let mut longs = Vec::new();
+            for _ in 0..len {
+                longs.push(read_i64(reader)?);
+            }
+            Tag::LongArray(longs)
+        }
+        _ => return Err(invalid(format!("Unknown NBT tag id {}", id))),
+    })
+}
+
+fn write_string(writer: &mut impl Write, value: &str) -> Result<()> {
+    if value.len() > u16::MAX as usize {
+        return Err(Error::new(ErrorKind::InvalidInput, "NBT string too long"));
+    }
+    writer.write_all(&(value.len() as u16).to_be_bytes())?;
+    writer.write_all(value.as_bytes())
+}
+
+fn write_payload(writer: &mut impl Write, tag: &Tag) -> Result<()> {
+    match tag {
+        Tag::Byte(v) => writer.write_all(&v.to_be_bytes()),
+        Tag::Short(v) => writer.write_all(&v.to_be_bytes()),
+        Tag::Int(v) => writer.write_all(&v.to_be_bytes()),
+        Tag::Long(v) => writer.write_all(&v.to_be_bytes()),
+        Tag::Float(v) => writer.write_all(&v.to_be_bytes()),
+        Tag::Double(v) => writer.write_all(&v.to_be_bytes()),
+        Tag::ByteArray(bytes) => {
+            writer.write_all(&(bytes.len() as i32).to_be_bytes())?;
+            writer.write_all(bytes)
+        }
+        Tag::String(v) => write_string(writer, v),
+        Tag::List(list) => {
+            let element = list.first().map(Tag::id).unwrap_or(0);
+            if list.iter().any(|tag| tag.id() != element) {
+                return Err(Error::new(ErrorKind::InvalidInput, "NBT list elements must share a type"));
+            }
+            writer.write_all(&[element])?;
+            writer.write_all(&(list.len() as i32).to_be_bytes())?;
+            for tag in list {
+                write_payload(writer, tag)?;
+            }
+            Ok(())
+        }
+        Tag::Compound(map) => {
+            for (name, tag) in map {
+                writer.write_all(&[tag.id()])?;
+                write_string(writer, name)?;
+                write_payload(writer, tag)?;
+            }
+            writer.write_all(&[0])
+        }
+        Tag::IntArray(ints) => {
+            writer.write_all(&(ints.len() as i32).to_be_bytes())?;
+            for v in ints {
+                writer.write_all(&v.to_be_bytes())?;
+            }
+            Ok(())
+        }
+        Tag::LongArray(longs) => {
+            writer.write_all(&(longs.len() as i32).to_be_bytes())?;
+            for v in longs {
+                writer.write_all(&v.to_be_bytes())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/**
+ * Decodes a byte array of LEB128 style varints, as used for block data.
+ */
+pub fn decode_varints(bytes: &[u8]) -> Result<Vec<u32>> {
+    let mut values = Vec::new();
+    let mut value: u32 = 0;
+    let mut shift = 0;
+
+    for byte in bytes {
+        if shift >= 32 {
+            return Err(invalid("Oversized varint".to_string()));
+        }
+        value |= ((byte & 0x7F) as u32) << shift;
+        if byte & 0x80 == 0 {
+            values.push(value);
+            value = 0;
+            shift = 0;
+        } else {
+            shift += 7;
+        }
+    }
+
+    if shift != 0 {
+        return Err(invalid("Byte array ends mid varint".to_string()));
+    }
+    Ok(values)
+}
+
+pub fn encode_varints(values: &[u32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(values.len());
+    for &value in values {
+        let mut value = value;
+        while value >= 0x80 {
+            bytes.push((value as u8 & 0x7F) | 0x80);
+            value >>= 7;
+        }
+        bytes.push(value as u8);
+    }
+    bytes
+}
+
+/**
+ * Reads an uncompressed root tag, returning its name and value.
+ */
+pub fn read(reader: &mut impl Read) -> Result<(String, Tag)> {
+    let id = read_u8(reader)?;
+    if id == 0 {
+        return Err(invalid("NBT root is an end tag".to_string()));
+    }
+    let name = read_string(reader)?;
+    Ok((name, read_payload(reader, id, 0)?))
+}
+
+pub fn write(writer: &mut impl Write, name: &str, tag: &Tag) -> Result<()> {
+    writer.write_all(&[tag.id()])?;
+    write_string(writer, name)?;
+    write_payload(writer, tag)
+}
+
+pub fn read_gzip(reader: impl Read) -> Result<(String, Tag)> {
+    read(&mut GzDecoder::new(reader))
+}
+
+pub fn write_gzip(writer: impl Write, name: &str, tag: &Tag) -> Result<()> {
+    let mut encoder = GzEncoder::new(writer, Compression::default());
+    write(&mut encoder, name, tag)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_every_tag() {
+        let mut compound = BTreeMap::new();
+        compound.insert("byte".to_string(), Tag::Byte(-3));
+        compound.insert("short".to_string(), Tag::Short(-300));
+        compound.insert("int".to_string(), Tag::Int(1 << 20));
+        compound.insert("long".to_string(), Tag::Long(-(1 << 40)));
+        compound.insert("float".to_string(), Tag::Float(0.5));
+        compound.insert("double".to_string(), Tag::Double(-2.25));
+        compound.insert("bytes".to_string(), Tag::ByteArray(vec![0, 127, 255]));
+        compound.insert("string".to_string(), Tag::String("minecraft:stone".to_string()));
+        compound.insert("list".to_string(), Tag::List(vec![Tag::Int(1), Tag::Int(2)]));
+        compound.insert("empty".to_string(), Tag::List(vec![]));
+        compound.insert("ints".to_string(), Tag::IntArray(vec![-1, 0, 1]));
+        compound.insert("longs".to_string(), Tag::LongArray(vec![i64::MIN, i64::MAX]));
+        let tag = Tag::Compound(compound);
+
+        let mut bytes = vec![];
+        write_gzip(&mut bytes, "root", &tag).unwrap();
+        assert_eq!(read_gzip(bytes.as_slice()).unwrap(), ("root".to_string(), tag));
+    }
+
+    #[test]
+    fn round_trips_varints() {
+        let values = [0, 1, 127, 128, 255, 16_383, 16_384, u32::MAX];
+        let bytes = encode_varints(&values);
+        assert_eq!(bytes.len(), 1 + 1 + 1 + 2 + 2 + 2 + 3 + 5);
+        assert_eq!(decode_varints(&bytes).unwrap(), values);
+        assert!(decode_varints(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
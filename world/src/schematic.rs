@@ -0,0 +1,524 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use log::warn;
+
+use math::point::Coord3DI;
+
+use crate::material::Material;
+use crate::nbt;
+use crate::nbt::{Tag, decode_varints, encode_varints};
+use crate::world::World;
+
+const AIR: &str = "minecraft:air";
+// Minecraft 1.20.1, the data version tools expect for the block ids we write
+const DATA_VERSION: i32 = 3465;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SchematicVersion {
+    V2,
+    V3,
+}
+
+/**
+ * Rotation around the Y axis, clockwise when looking down.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rotation {
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mirror {
+    None,
+    // Flips the X axis
+    X,
+    // Flips the Z axis
+    Z,
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockEntity {
+    pub pos: Coord3DI,
+    pub id: String,
+    pub data: BTreeMap<String, Tag>,
+}
+
+/**
+ * A rectangular region of blocks in the Sponge schematic format (versions 2 and 3).
+ * Blocks are stored as palette indices ordered by x, then z, then y.
+ */
+pub struct Schematic {
+    pub width: u16,
+    pub height: u16,
+    pub length: u16,
+    pub offset: Coord3DI,
+    pub palette: Vec<String>,
+    pub blocks: Vec<u32>,
+    pub block_entities: Vec<BlockEntity>,
+}
+
+fn missing(field: &str) -> Box<dyn Error> {
+    format!("Schematic is missing or has an invalid {}", field).into()
+}
+
+fn is_air(name: &str) -> bool {
+    let id = name.split('[').next().unwrap_or(name);
+    id == AIR || id == "minecraft:cave_air" || id == "minecraft:void_air"
+}
+
+fn read_pos(tag: Option<&Tag>) -> Option<Coord3DI> {
+    match tag?.as_int_array()?.as_slice() {
+        [x, y, z] => Some(Coord3DI::new(*x, *y, *z)),
+        _ => None,
+    }
+}
+
+fn pos_tag(pos: Coord3DI) -> Tag {
+    Tag::IntArray(vec![pos.x, pos.y, pos.z])
+}
+
+fn transform(pos: Coord3DI, rotation: Rotation, mirror: Mirror) -> Coord3DI {
+    let (x, z) = match mirror {
+        Mirror::None => (pos.x, pos.z),
+        Mirror::X => (-pos.x, pos.z),
+        Mirror::Z => (pos.x, -pos.z),
+    };
+    let (x, z) = match rotation {
+        Rotation::None => (x, z),
+        Rotation::Clockwise90 => (-z, x),
+        Rotation::Clockwise180 => (-x, -z),
+        Rotation::Clockwise270 => (z, -x),
+    };
+    Coord3DI::new(x, pos.y, z)
+}
+
+impl Schematic {
+    /**
+     * Captures the blocks between two inclusive corners of the world.
+     */
+    pub fn from_world(world: &World, a: Coord3DI, b: Coord3DI) -> Result<Self, Box<dyn Error>> {
+        let min = Coord3DI::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Coord3DI::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+        let size = [max.x - min.x + 1, max.y - min.y + 1, max.z - min.z + 1];
+        if size.iter().any(|&s| s > u16::MAX as i32) {
+            return Err(format!("Schematic region {:?} is too large", size).into());
+        }
+
+        let mut palette = vec![AIR.to_string()];
+        let mut palette_lookup: HashMap<String, u32> = HashMap::new();
+        let mut blocks = Vec::with_capacity((size[0] * size[1] * size[2]) as usize);
+
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                for x in min.x..=max.x {
+                    let index = match world.get_block(Coord3DI::new(x, y, z)) {
+                        Some(block) => {
+                            let name = block.material.block_state();
+                            *palette_lookup.entry(name.clone()).or_insert_with(|| {
+                                palette.push(name);
+                                palette.len() as u32 - 1
+                            })
+                        }
+                        None => 0,
+                    };
+                    blocks.push(index);
+                }
+            }
+        }
+
+        Ok(Self {
+            width: size[0] as u16,
+            height: size[1] as u16,
+            length: size[2] as u16,
+            offset: Coord3DI::new(0, 0, 0),
+            palette,
+            blocks,
+            block_entities: Vec::new(),
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let (_, root) = nbt::read_gzip(BufReader::new(File::open(path)?))?;
+        Self::from_nbt(&root)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>, version: SchematicVersion) -> Result<(), Box<dyn Error>> {
+        let (name, root) = self.to_nbt(version);
+        nbt::write_gzip(BufWriter::new(File::create(path)?), &name, &root)?;
+        Ok(())
+    }
+
+    pub fn from_nbt(root: &Tag) -> Result<Self, Box<dyn Error>> {
+        // Version 3 wraps everything in a "Schematic" compound under an unnamed root
+        let schematic = match root.get("Schematic") {
+            Some(inner @ Tag::Compound(_)) => inner,
+            _ => root,
+        };
+
+        let version = schematic.get("Version").and_then(Tag::as_i64).ok_or_else(|| missing("Version"))?;
+        let dimension = |field: &str| -> Result<u16, Box<dyn Error>> {
+            // Dimensions are unsigned shorts stored in signed tags
+            let value = schematic.get(field).and_then(Tag::as_i64).ok_or_else(|| missing(field))?;
+            Ok(value as u16)
+        };
+        let width = dimension("Width")?;
+        let height = dimension("Height")?;
+        let length = dimension("Length")?;
+        let offset = match schematic.get("Offset") {
+            Some(tag) => read_pos(Some(tag)).ok_or_else(|| missing("Offset"))?,
+            None => Coord3DI::new(0, 0, 0),
+        };
+
+        let (palette_tag, data_tag, entities_tag) = match version {
+            2 => (schematic.get("Palette"), schematic.get("BlockData"), schematic.get("BlockEntities")),
+            3 => {
+                let container = schematic.get("Blocks").ok_or_else(|| missing("Blocks"))?;
+                (container.get("Palette"), container.get("Data"), container.get("BlockEntities"))
+            }
+            _ => return Err(format!("Unsupported schematic version {}", version).into()),
+        };
+
+        let palette_map = palette_tag.and_then(Tag::as_compound).ok_or_else(|| missing("Palette"))?;
+        let mut palette = vec![AIR.to_string(); palette_map.len()];
+        for (name, index) in palette_map {
+            let index = index.as_i64().ok_or_else(|| missing("Palette"))?;
+            if index < 0 || index as usize >= palette.len() {
+                return Err(format!("Schematic palette index {} for {} is out of range", index, name).into());
+            }
+            palette[index as usize] = name.clone();
+        }
+
+        let blocks = decode_varints(data_tag.and_then(Tag::as_bytes).ok_or_else(|| missing("BlockData"))?)?;
+        let volume = width as usize * height as usize * length as usize;
+        if blocks.len() != volume {
+            return Err(format!("Schematic has {} blocks but its size holds {}", blocks.len(), volume).into());
+        }
+        if let Some(bad) = blocks.iter().find(|&&index| index as usize >= palette.len()) {
+            return Err(format!("Schematic block refers to missing palette entry {}", bad).into());
+        }
+
+        let mut block_entities = Vec::new();
+        for entity in entities_tag.and_then(Tag::as_list).map(Vec::as_slice).unwrap_or_default() {
+            let fields = entity.as_compound().ok_or_else(|| missing("BlockEntities"))?;
+            let pos = read_pos(fields.get("Pos")).ok_or_else(|| missing("block entity Pos"))?;
+            let id = fields.get("Id").and_then(Tag::as_str).ok_or_else(|| missing("block entity Id"))?.to_string();
+            let data = match version {
+                2 => fields.iter()
+                    .filter(|(key, _)| key.as_str() != "Pos" && key.as_str() != "Id")
+                    .map(|(key, tag)| (key.clone(), tag.clone()))
+                    .collect(),
+                _ => fields.get("Data").and_then(Tag::as_compound).cloned().unwrap_or_default(),
+            };
+            block_entities.push(BlockEntity { pos, id, data });
+        }
+
+        Ok(Self {
+            width,
+            height,
+            length,
+            offset,
+            palette,
+            blocks,
+            block_entities,
+        })
+    }
+
+    /**
+     * Builds the root tag for the given format version, returning it with its root name.
+     */
+    pub fn to_nbt(&self, version: SchematicVersion) -> (String, Tag) {
+        let palette: BTreeMap<String, Tag> = self.palette.iter()
+            .enumerate()
+            .map(|(index, name)| (name.clone(), Tag::Int(index as i32)))
+            .collect();
+
+        let block_entities: Vec<Tag> = self.block_entities.iter()
+            .map(|entity| {
+                let mut fields = BTreeMap::new();
+                fields.insert("Pos".to_string(), pos_tag(entity.pos));
+                fields.insert("Id".to_string(), Tag::String(entity.id.clone()));
+                match version {
+                    SchematicVersion::V2 => fields.extend(entity.data.clone()),
+                    SchematicVersion::V3 => {
+                        fields.insert("Data".to_string(), Tag::Compound(entity.data.clone()));
+                    }
+                }
+                Tag::Compound(fields)
+            })
+            .collect();
+
+        let mut schematic = BTreeMap::new();
+        schematic.insert("DataVersion".to_string(), Tag::Int(DATA_VERSION));
+        schematic.insert("Width".to_string(), Tag::Short(self.width as i16));
+        schematic.insert("Height".to_string(), Tag::Short(self.height as i16));
+        schematic.insert("Length".to_string(), Tag::Short(self.length as i16));
+        schematic.insert("Offset".to_string(), pos_tag(self.offset));
+
+        match version {
+            SchematicVersion::V2 => {
+                schematic.insert("Version".to_string(), Tag::Int(2));
+                schematic.insert("PaletteMax".to_string(), Tag::Int(self.palette.len() as i32));
+                schematic.insert("Palette".to_string(), Tag::Compound(palette));
+                schematic.insert("BlockData".to_string(), Tag::ByteArray(encode_varints(&self.blocks)));
+                schematic.insert("BlockEntities".to_string(), Tag::List(block_entities));
+                ("Schematic".to_string(), Tag::Compound(schematic))
+            }
+            SchematicVersion::V3 => {
+                let mut blocks = BTreeMap::new();
+                blocks.insert("Palette".to_string(), Tag::Compound(palette));
+                blocks.insert("Data".to_string(), Tag::ByteArray(encode_varints(&self.blocks)));
+                blocks.insert("BlockEntities".to_string(), Tag::List(block_entities));
+
+                schematic.insert("Version".to_string(), Tag::Int(3));
+                schematic.insert("Blocks".to_string(), Tag::Compound(blocks));
+
+                let mut root = BTreeMap::new();
+                root.insert("Schematic".to_string(), Tag::Compound(schematic));
+                (String::new(), Tag::Compound(root))
+            }
+        }
+    }
+
+    /**
+     * Returns the palette entry at a position relative to the schematic's minimum corner.
+     */
+    pub fn block(&self, x: u16, y: u16, z: u16) -> Option<&str> {
+        if x >= self.width || y >= self.height || z >= self.length {
+            return None;
+        }
+        let index = x as usize
+            + z as usize * self.width as usize
+            + y as usize * self.width as usize * self.length as usize;
+        Some(&self.palette[self.blocks[index] as usize])
+    }
+
+    /**
+     * Pastes the schematic so its origin (minimum corner minus `offset`) lands on `pos`, mirroring
+     * and then rotating around that origin. Air in the schematic clears existing blocks.
+     * Blocks without a registered material are skipped.
+     *
+     * Block entities are returned in world space, since the world has nowhere to store them yet.
+     */
+    pub fn paste(&self, world: &mut World, pos: Coord3DI, rotation: Rotation, mirror: Mirror) -> Vec<BlockEntity> {
+        // None skips the block, Some(None) clears it to air
+        let materials: Vec<Option<Option<Material>>> = self.palette.iter()
+            .map(|name| match Material::from_name(name) {
+                Some(material) => Some(Some(material)),
+                None if is_air(name) => Some(None),
+                None => {
+                    warn!("Skipping unknown schematic block {}", name);
+                    None
+                }
+            })
+            .collect();
+
+        let place = |relative: Coord3DI| {
+            let local = Coord3DI::new(relative.x + self.offset.x, relative.y + self.offset.y, relative.z + self.offset.z);
+            let rotated = transform(local, rotation, mirror);
+            Coord3DI::new(pos.x + rotated.x, pos.y + rotated.y, pos.z + rotated.z)
+        };
+
+        let mut blocks = Vec::with_capacity(self.blocks.len());
+        let mut index = 0;
+        for y in 0..self.height as i32 {
+            for z in 0..self.length as i32 {
+                for x in 0..self.width as i32 {
+                    let material = materials[self.blocks[index] as usize];
+                    index += 1;
+
+                    if let Some(material) = material {
+                        blocks.push((place(Coord3DI::new(x, y, z)), material));
+                    }
+                }
+            }
+        }
+        world.set_blocks(blocks);
+
+        self.block_entities.iter()
+            .map(|entity| BlockEntity {
+                pos: place(entity.pos),
+                id: entity.id.clone(),
+                data: entity.data.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use math::point::Coord2DI;
+
+    use crate::chunk::Chunk;
+    use crate::light::LightChannel;
+
+    use super::*;
+
+    // A palette too wide for every index to fit in a single varint byte
+    fn wide_schematic() -> Schematic {
+        let palette: Vec<String> = (0..200).map(|i| format!("minecraft:block_{}", i)).collect();
+        let (width, height, length) = (5, 4, 15);
+        let blocks = (0..width as u32 * height as u32 * length as u32).map(|i| i * 7 % palette.len() as u32).collect();
+        let mut data = BTreeMap::new();
+        data.insert("Text".to_string(), Tag::String("hello".to_string()));
+        Schematic {
+            width,
+            height,
+            length,
+            offset: Coord3DI::new(-1, 2, -3),
+            palette,
+            blocks,
+            block_entities: vec![BlockEntity { pos: Coord3DI::new(1, 2, 3), id: "minecraft:sign".to_string(), data }],
+        }
+    }
+
+    fn encode(schematic: &Schematic, version: SchematicVersion) -> Vec<u8> {
+        let (name, root) = schematic.to_nbt(version);
+        let mut bytes = vec![];
+        nbt::write_gzip(&mut bytes, &name, &root).unwrap();
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Schematic, Box<dyn Error>> {
+        let (_, root) = nbt::read_gzip(bytes)?;
+        Schematic::from_nbt(&root)
+    }
+
+    fn round_trip(version: SchematicVersion) {
+        let schematic = wide_schematic();
+        let read = decode(&encode(&schematic, version)).unwrap();
+        assert_eq!((read.width, read.height, read.length), (schematic.width, schematic.height, schematic.length));
+        assert_eq!(read.offset, schematic.offset);
+        assert_eq!(read.palette, schematic.palette);
+        assert_eq!(read.blocks, schematic.blocks);
+        assert_eq!(read.block(4, 3, 14), schematic.block(4, 3, 14));
+
+        let [entity] = read.block_entities.as_slice() else { panic!("Expected one block entity") };
+        assert_eq!(entity.pos, Coord3DI::new(1, 2, 3));
+        assert_eq!(entity.id, "minecraft:sign");
+        assert_eq!(entity.data, schematic.block_entities[0].data);
+    }
+
+    #[test]
+    fn round_trips_version_2() {
+        round_trip(SchematicVersion::V2);
+    }
+
+    #[test]
+    fn round_trips_version_3() {
+        round_trip(SchematicVersion::V3);
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = encode(&wide_schematic(), SchematicVersion::V3);
+        assert!(decode(&bytes[..bytes.len() / 2]).is_err());
+    }
+
+    // Stone at the origin with dirt east of it and glowstone south of it, air everywhere else,
+    // and a block entity at the south east corner
+    fn corner_schematic() -> Schematic {
+        Schematic {
+            width: 3,
+            height: 1,
+            length: 2,
+            offset: Coord3DI::new(0, 0, 0),
+            palette: vec![AIR.to_string(), "minecraft:stone".to_string(), "minecraft:dirt".to_string(), "minecraft:glowstone".to_string()],
+            blocks: vec![1, 2, 0, 3, 0, 0],
+            block_entities: vec![BlockEntity { pos: Coord3DI::new(1, 0, 1), id: "minecraft:chest".to_string(), data: BTreeMap::new() }],
+        }
+    }
+
+    fn name_at(world: &World, pos: Coord3DI) -> Option<String> {
+        world.get_block(pos).map(|block| block.material.block_state())
+    }
+
+    #[test]
+    fn pastes_rotated_and_mirrored() {
+        let origin = Coord3DI::new(8, 70, 8);
+        let at = |x, z| Coord3DI::new(origin.x + x, origin.y, origin.z + z);
+        // Where dirt, glowstone and the block entity end up, relative to the origin
+        let cases = [
+            (Rotation::None, Mirror::None, (1, 0), (0, 1), (1, 1)),
+            (Rotation::Clockwise90, Mirror::None, (0, 1), (-1, 0), (-1, 1)),
+            (Rotation::Clockwise180, Mirror::None, (-1, 0), (0, -1), (-1, -1)),
+            (Rotation::Clockwise270, Mirror::None, (0, -1), (1, 0), (1, -1)),
+            (Rotation::None, Mirror::X, (-1, 0), (0, 1), (-1, 1)),
+            (Rotation::None, Mirror::Z, (1, 0), (0, -1), (1, -1)),
+            (Rotation::Clockwise90, Mirror::X, (0, -1), (-1, 0), (-1, -1)),
+        ];
+
+        for (rotation, mirror, dirt, glowstone, entity) in cases {
+            let mut world = World::new();
+            let entities = corner_schematic().paste(&mut world, origin, rotation, mirror);
+
+            let placed: Vec<_> = world.chunks().flat_map(|chunk| chunk.blocks().iter().map(|block| World::to_world(chunk.pos, block.pos))).collect();
+            assert_eq!(placed.len(), 3, "{:?} {:?}", rotation, mirror);
+            assert_eq!(name_at(&world, origin).as_deref(), Some("minecraft:stone"));
+            assert_eq!(name_at(&world, at(dirt.0, dirt.1)).as_deref(), Some("minecraft:dirt"), "{:?} {:?}", rotation, mirror);
+            assert_eq!(name_at(&world, at(glowstone.0, glowstone.1)).as_deref(), Some("minecraft:glowstone"), "{:?} {:?}", rotation, mirror);
+            assert_eq!(entities[0].pos, at(entity.0, entity.1), "{:?} {:?}", rotation, mirror);
+        }
+    }
+
+    #[test]
+    fn pastes_at_the_offset_and_clears_air() {
+        let mut world = World::new();
+        let origin = Coord3DI::new(8, 70, 8);
+        world.set_block(Coord3DI::new(7, 70, 8), Some(Material::from_name("minecraft:stone").unwrap()));
+
+        let schematic = Schematic { offset: Coord3DI::new(-3, 0, 0), ..corner_schematic() };
+        schematic.paste(&mut world, origin, Rotation::None, Mirror::None);
+        assert_eq!(name_at(&world, Coord3DI::new(5, 70, 8)).as_deref(), Some("minecraft:stone"));
+        assert_eq!(name_at(&world, Coord3DI::new(6, 70, 8)).as_deref(), Some("minecraft:dirt"));
+        assert_eq!(name_at(&world, Coord3DI::new(7, 70, 8)), None);
+    }
+
+    #[test]
+    fn captures_and_pastes_back() {
+        let mut source = World::new();
+        corner_schematic().paste(&mut source, Coord3DI::new(8, 70, 8), Rotation::None, Mirror::None);
+
+        let captured = Schematic::from_world(&source, Coord3DI::new(10, 71, 9), Coord3DI::new(8, 70, 8)).unwrap();
+        assert_eq!((captured.width, captured.height, captured.length), (3, 2, 2));
+        assert_eq!(captured.block(1, 0, 0), Some("minecraft:dirt"));
+        assert_eq!(captured.block(1, 1, 0), Some(AIR));
+
+        let mut target = World::new();
+        captured.paste(&mut target, Coord3DI::new(-20, 100, 30), Rotation::None, Mirror::None);
+        for (x, y, z) in [(0, 0, 0), (1, 0, 0), (2, 0, 0), (0, 0, 1), (1, 1, 1)] {
+            assert_eq!(name_at(&target, Coord3DI::new(-20 + x, 100 + y, 30 + z)), name_at(&source, Coord3DI::new(8 + x, 70 + y, 8 + z)));
+        }
+    }
+
+    #[test]
+    fn pasting_lights_like_a_fresh_chunk() {
+        let mut pasted = World::new();
+        pasted.insert_chunk(Chunk::new(Coord2DI::new(0, 0)));
+        corner_schematic().paste(&mut pasted, Coord3DI::new(8, 70, 8), Rotation::None, Mirror::None);
+
+        let mut chunk = Chunk::new(Coord2DI::new(0, 0));
+        for block in pasted.get_chunk(Coord2DI::new(0, 0)).unwrap().blocks() {
+            chunk.set_block(block.pos, Some(block.material));
+        }
+        let mut fresh = World::new();
+        fresh.insert_chunk(chunk);
+
+        for y in 50..90 {
+            for z in 0..16 {
+                for x in 0..16 {
+                    let pos = Coord3DI::new(x, y, z);
+                    for channel in [LightChannel::Sky, LightChannel::Block] {
+                        assert_eq!(pasted.get_light(pos, channel), fresh.get_light(pos, channel), "{:?} at {:?}", channel, pos);
+                    }
+                }
+            }
+        }
+    }
+}
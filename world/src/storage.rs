@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+use math::{CHUNK_HEIGHT, CHUNK_SIZE};
+use math::point::{Coord2DI, Coord3DI, Indexable};
+
+use crate::chunk::Chunk;
+use crate::clock::{NOON, WorldClock};
+use crate::material::Material;
+use crate::nbt;
+use crate::nbt::{Tag, decode_varints, encode_varints};
+use crate::world::World;
+
+const LEVEL_FILE: &str = "level.nbt";
+const CHUNK_DIRECTORY: &str = "chunks";
+const PENDING_SUFFIX: &str = ".nbt.tmp";
+
+/**
+ * Metadata describing a saved world.
+ */
+#[derive(Clone, Debug)]
+pub struct Level {
+    pub seed: u32,
+    // Ticks since the world was created, see `WorldClock`
+    pub time: u64,
+}
+
+/**
+ * A world saved to a directory, as a level file plus one gzipped NBT file per chunk.
+ */
+pub struct WorldStorage {
+    dir: PathBuf,
+}
+
+impl WorldStorage {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn exists(&self) -> bool {
+        self.dir.join(LEVEL_FILE).is_file()
+    }
+
+    pub fn load_level(&self) -> Result<Level, Box<dyn Error>> {
+        let (_, root) = nbt::read_gzip(BufReader::new(File::open(self.dir.join(LEVEL_FILE))?))?;
+        let seed = root.get("Seed").and_then(Tag::as_i64).ok_or("Level is missing its seed")?;
+
+        // Worlds saved before the day night cycle start at noon
+        let time = root.get("Time").and_then(Tag::as_i64).map_or(NOON, |time| time as u64);
+
+        Ok(Level {
+            seed: seed as u32,
+            time,
+        })
+    }
+
+    pub fn save_level(&self, level: &Level) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)?;
+
+        let mut root = BTreeMap::new();
+        root.insert("Seed".to_string(), Tag::Long(level.seed as i64));
+        root.insert("Time".to_string(), Tag::Long(level.time as i64));
+
+        nbt::write_gzip(BufWriter::new(File::create(self.dir.join(LEVEL_FILE))?), "Level", &Tag::Compound(root))?;
+        Ok(())
+    }
+
+    pub fn chunk_path(&self, pos: Coord2DI) -> PathBuf {
+        self.dir.join(CHUNK_DIRECTORY).join(format!("{}.{}.nbt", pos.x, pos.y))
+    }
+
+    fn scan_chunk_directory(&self, suffix: &str) -> Result<Vec<Coord2DI>, Box<dyn Error>> {
+        let dir = self.dir.join(CHUNK_DIRECTORY);
+        if !dir.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut positions = vec![];
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            let name = name.to_string_lossy();
+            if name.ends_with(PENDING_SUFFIX) && suffix != PENDING_SUFFIX {
+                continue;
+            }
+
+            let parsed = name.strip_suffix(suffix)
+                .and_then(|name| name.split_once('.'))
+                .and_then(|(x, z)| Some(Coord2DI::new(x.parse().ok()?, z.parse().ok()?)));
+
+            match parsed {
+                Some(pos) => positions.push(pos),
+                None if suffix == PENDING_SUFFIX => {}
+                None => warn!("Ignoring unexpected file {:?} in chunk directory", name),
+            }
+        }
+
+        Ok(positions)
+    }
+
+    /**
+     * Lists the positions of every chunk file in the world, without reading them.
+     */
+    pub fn chunk_positions(&self) -> Result<Vec<Coord2DI>, Box<dyn Error>> {
+        self.scan_chunk_directory(".nbt")
+    }
+
+    /**
+     * Lists chunks whose last save never completed, e.g. because the game crashed mid write.
+     * Their previous save, if any, is still intact.
+     */
+    pub fn pending_chunk_positions(&self) -> Result<Vec<Coord2DI>, Box<dyn Error>> {
+        self.scan_chunk_directory(PENDING_SUFFIX)
+    }
+
+    /**
+     * Reads a chunk, `Ok(None)` meaning it was never saved.
+     */
+    pub fn load_chunk(&self, pos: Coord2DI) -> Result<Option<Chunk>, Box<dyn Error>> {
+        let path = self.chunk_path(pos);
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        let (_, root) = nbt::read_gzip(BufReader::new(File::open(path)?))?;
+        Ok(Some(chunk_from_nbt(pos, &root)?))
+    }
+
+    /**
+     * Writes the chunk to a pending file first and renames it over the old save once complete,
+     * so a crash can never leave a half written chunk behind.
+     */
+    pub fn save_chunk(&self, chunk: &Chunk) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(self.dir.join(CHUNK_DIRECTORY))?;
+
+        let path = self.chunk_path(chunk.pos);
+        let pending = path.with_extension(&PENDING_SUFFIX[1..]);
+        nbt::write_gzip(BufWriter::new(File::create(&pending)?), "Chunk", &chunk_to_nbt(chunk))?;
+        fs::rename(pending, path)?;
+        Ok(())
+    }
+
+    /**
+     * Loads every saved chunk and the time of day. Chunks that fail to load are logged and skipped.
+     */
+    pub fn load_world(&self) -> Result<World, Box<dyn Error>> {
+        let mut world = World::new();
+        world.clock = WorldClock::new(self.load_level()?.time);
+        for pos in self.chunk_positions()? {
+            match self.load_chunk(pos) {
+                Ok(Some(chunk)) => world.insert_chunk(chunk),
+                Ok(None) => {}
+                Err(e) => warn!("Failed to load chunk {} {}: {}", pos.x, pos.y, e),
+            }
+        }
+        Ok(world)
+    }
+
+    pub fn save_world(&self, world: &World) -> Result<(), Box<dyn Error>> {
+        for chunk in world.chunks() {
+            self.save_chunk(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+fn chunk_to_nbt(chunk: &Chunk) -> Tag {
+    // Palette index 0 is air, blocks are ordered like `math::point::index`
+    let mut palette: Vec<String> = vec![];
+    let mut blocks = vec![0; CHUNK_SIZE * CHUNK_SIZE * CHUNK_HEIGHT];
+
+    for block in chunk.blocks() {
+        let name = block.material.block_state();
+        let index = match palette.iter().position(|entry| *entry == name) {
+            Some(index) => index,
+            None => {
+                palette.push(name);
+                palette.len() - 1
+            }
+        };
+        blocks[block.pos.index()] = index as u32 + 1;
+    }
+
+    let mut root = BTreeMap::new();
+    root.insert("Pos".to_string(), Tag::IntArray(vec![chunk.pos.x, chunk.pos.y]));
+    root.insert("Palette".to_string(), Tag::List(palette.into_iter().map(Tag::String).collect()));
+    root.insert("Blocks".to_string(), Tag::ByteArray(encode_varints(&blocks)));
+    Tag::Compound(root)
+}
+
+fn chunk_from_nbt(pos: Coord2DI, root: &Tag) -> Result<Chunk, Box<dyn Error>> {
+    match root.get("Pos").and_then(Tag::as_int_array).map(Vec::as_slice) {
+        Some([x, z]) if *x == pos.x && *z == pos.y => {}
+        _ => return Err(format!("Chunk file for {} {} has a mismatched position", pos.x, pos.y).into()),
+    }
+
+    let palette = root.get("Palette").and_then(Tag::as_list).ok_or("Chunk is missing its palette")?;
+    let materials = palette.iter()
+        .map(|entry| {
+            let name = entry.as_str().ok_or("Chunk palette entry is not a string")?;
+            Material::from_name(name).ok_or_else(|| format!("Unknown block {} in chunk palette", name))
+        })
+        .collect::<Result<Vec<Material>, String>>()?;
+
+    let blocks = decode_varints(root.get("Blocks").and_then(Tag::as_bytes).ok_or("Chunk is missing its blocks")?)?;
+    if blocks.len() != CHUNK_SIZE * CHUNK_SIZE * CHUNK_HEIGHT {
+        return Err(format!("Chunk has {} blocks instead of {}", blocks.len(), CHUNK_SIZE * CHUNK_SIZE * CHUNK_HEIGHT).into());
+    }
+
+    let mut chunk = Chunk::new(pos);
+    for (i, &entry) in blocks.iter().enumerate() {
+        if entry == 0 {
+            continue;
+        }
+
+        let material = *materials.get(entry as usize - 1).ok_or("Chunk block refers to a missing palette entry")?;
+        // Inverse of `math::point::index`
+        let local = Coord3DI::new((i & 0xF) as i32, (i >> 8) as i32, ((i >> 4) & 0xF) as i32);
+        chunk.set_block(local, Some(material));
+    }
+
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::material::{GLASS, GLOWSTONE, STONE, WATER};
+
+    use super::*;
+
+    fn temp_storage(name: &str) -> WorldStorage {
+        let dir = std::env::temp_dir().join(format!("storage-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        WorldStorage::new(dir)
+    }
+
+    fn test_chunk(pos: Coord2DI) -> Chunk {
+        let mut chunk = Chunk::new(pos);
+        chunk.set_block(Coord3DI::new(0, 0, 0), Some(STONE));
+        chunk.set_block(Coord3DI::new(15, 255, 15), Some(GLOWSTONE));
+        chunk.set_block(Coord3DI::new(3, 64, 9), Some(GLASS));
+        chunk.set_block(Coord3DI::new(4, 64, 9), Some(WATER.with_level(3)));
+        chunk
+    }
+
+    fn contents(chunk: &Chunk) -> Vec<(usize, String)> {
+        let mut blocks: Vec<_> = chunk.blocks().iter().map(|block| (block.pos.index(), block.material.block_state())).collect();
+        blocks.sort();
+        blocks
+    }
+
+    #[test]
+    fn saved_chunks_load_back_unchanged() {
+        let storage = temp_storage("round-trip");
+        let chunk = test_chunk(Coord2DI::new(-3, 7));
+        storage.save_chunk(&chunk).unwrap();
+
+        assert_eq!(storage.chunk_positions().unwrap(), vec![chunk.pos]);
+        assert!(storage.pending_chunk_positions().unwrap().is_empty());
+        let loaded = storage.load_chunk(chunk.pos).unwrap().unwrap();
+        assert_eq!(loaded.pos, chunk.pos);
+        assert_eq!(contents(&loaded), contents(&chunk));
+        assert!(storage.load_chunk(Coord2DI::new(0, 0)).unwrap().is_none());
+
+        fs::remove_dir_all(storage.dir()).unwrap();
+    }
+
+    #[test]
+    fn interrupted_saves_keep_the_previous_save() {
+        let storage = temp_storage("pending");
+        let chunk = test_chunk(Coord2DI::new(2, -1));
+        storage.save_chunk(&chunk).unwrap();
+        // What a crash halfway through the next save leaves behind
+        fs::write(storage.chunk_path(chunk.pos).with_extension(&PENDING_SUFFIX[1..]), [0x1f, 0x8b, 0]).unwrap();
+
+        assert_eq!(storage.pending_chunk_positions().unwrap(), vec![chunk.pos]);
+        assert_eq!(storage.chunk_positions().unwrap(), vec![chunk.pos]);
+        assert_eq!(contents(&storage.load_chunk(chunk.pos).unwrap().unwrap()), contents(&chunk));
+
+        fs::remove_dir_all(storage.dir()).unwrap();
+    }
+}
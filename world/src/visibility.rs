@@ -0,0 +1,148 @@
+use std::collections::VecDeque;
+
+use math::{CHUNK_HEIGHT, CHUNK_SIZE};
+use math::point::Coord3DI;
+
+use crate::chunk::Chunk;
+use crate::light::{neighbour, NEIGHBOURS};
+
+// Sections are cubes stacked up a chunk, the unit caves are culled in
+pub const SECTION_SIZE: usize = CHUNK_SIZE;
+pub const SECTIONS: usize = CHUNK_HEIGHT / SECTION_SIZE;
+
+/**
+ * Which faces of a section can see each other through the blocks inside it that aren't opaque,
+ * with faces indexed like `Face`. Anything looking in through one face can only see out of the
+ * faces it connects to, which is what lets caves behind solid rock be left undrawn.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SectionVisibility(u64);
+
+impl SectionVisibility {
+    // Every face sees every other, as in a section of only air
+    pub const ALL: Self = Self((1 << (NEIGHBOURS.len() * NEIGHBOURS.len())) - 1);
+
+    pub fn connects(self, from: usize, to: usize) -> bool {
+        self.0 & (1 << (from * NEIGHBOURS.len() + to)) != 0
+    }
+
+    /**
+     * Flood fills each open space of a section and connects all the faces it touches.
+     */
+    pub fn compute(chunk: &Chunk, section: usize) -> Self {
+        let size = SECTION_SIZE as i32;
+        let bottom = (section * SECTION_SIZE) as i32;
+        let cell = |pos: Coord3DI| (((pos.y - bottom) * size + pos.z) * size + pos.x) as usize;
+        let open = |pos: Coord3DI| chunk.block_at(pos.x, pos.y, pos.z).is_none_or(|block| !block.material.is_opaque());
+
+        let mut visited = vec![false; SECTION_SIZE * SECTION_SIZE * SECTION_SIZE];
+        let mut visibility = 0u64;
+        let mut queue = VecDeque::new();
+        for y in bottom..bottom + size {
+            for z in 0..size {
+                for x in 0..size {
+                    let start = Coord3DI::new(x, y, z);
+                    if visited[cell(start)] || !open(start) {
+                        continue;
+                    }
+
+                    // Faces this open space reaches, as bits
+                    let mut faces = 0u64;
+                    visited[cell(start)] = true;
+                    queue.push_back(start);
+                    while let Some(pos) = queue.pop_front() {
+                        for face in 0..NEIGHBOURS.len() {
+                            let next = neighbour(pos, face);
+                            if !(0..size).contains(&next.x) || !(bottom..bottom + size).contains(&next.y) || !(0..size).contains(&next.z) {
+                                faces |= 1 << face;
+                            } else if !visited[cell(next)] && open(next) {
+                                visited[cell(next)] = true;
+                                queue.push_back(next);
+                            }
+                        }
+                    }
+
+                    for from in 0..NEIGHBOURS.len() {
+                        if faces & (1 << from) != 0 {
+                            visibility |= faces << (from * NEIGHBOURS.len());
+                        }
+                    }
+                }
+            }
+        }
+        Self(visibility)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use math::face::Face;
+    use math::point::Coord2DI;
+
+    use crate::material::STONE;
+
+    use super::*;
+
+    fn connections(visibility: SectionVisibility) -> Vec<(usize, usize)> {
+        let faces = 0..NEIGHBOURS.len();
+        faces.clone().flat_map(|from| faces.clone().map(move |to| (from, to)))
+            .filter(|&(from, to)| visibility.connects(from, to))
+            .collect()
+    }
+
+    // The first section filled with stone, except for the given cells
+    fn section_except(open: impl Fn(Coord3DI) -> bool) -> Chunk {
+        let mut chunk = Chunk::new(Coord2DI::new(0, 0));
+        let size = SECTION_SIZE as i32;
+        for y in 0..size {
+            for z in 0..size {
+                for x in 0..size {
+                    let pos = Coord3DI::new(x, y, z);
+                    if !open(pos) {
+                        chunk.set_block(pos, Some(STONE));
+                    }
+                }
+            }
+        }
+        chunk
+    }
+
+    #[test]
+    fn solid_sections_connect_nothing() {
+        let chunk = section_except(|_| false);
+        assert_eq!(connections(SectionVisibility::compute(&chunk, 0)), vec![]);
+    }
+
+    #[test]
+    fn open_sections_connect_every_face() {
+        let chunk = Chunk::new(Coord2DI::new(0, 0));
+        assert_eq!(SectionVisibility::compute(&chunk, 0), SectionVisibility::ALL);
+    }
+
+    #[test]
+    fn tunnels_connect_their_ends() {
+        let chunk = section_except(|pos| pos.y == 5 && pos.z == 7);
+        let (left, right) = (Face::Left as usize, Face::Right as usize);
+        assert_eq!(connections(SectionVisibility::compute(&chunk, 0)), vec![(left, left), (left, right), (right, left), (right, right)]);
+    }
+
+    #[test]
+    fn sealed_caves_connect_nothing() {
+        let chunk = section_except(|pos| (4..12).contains(&pos.x) && (4..12).contains(&pos.y) && (4..12).contains(&pos.z));
+        assert_eq!(connections(SectionVisibility::compute(&chunk, 0)), vec![]);
+    }
+
+    #[test]
+    fn chunks_catch_up_with_edits_when_asked() {
+        let mut chunk = section_except(|_| false);
+        assert_eq!(chunk.section_visibility(0), SectionVisibility::compute(&chunk, 0));
+
+        // Bore a tunnel through the section after its visibility was worked out
+        for x in 0..SECTION_SIZE as i32 {
+            chunk.set_block(Coord3DI::new(x, 5, 7), None);
+        }
+        let (left, right) = (Face::Left as usize, Face::Right as usize);
+        assert!(chunk.section_visibility(0).connects(left, right));
+        assert_eq!(chunk.section_visibility(1), SectionVisibility::ALL);
+    }
+}
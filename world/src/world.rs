@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::collections::hash_map::{Values, ValuesMut};
+
+use math::{CHUNK_HEIGHT, CHUNK_SIZE};
+use math::point::{Coord2DI, Coord3DI};
+
+use crate::block::Block;
+use crate::chunk::{Chunk, ChunkGenerator};
+use crate::clock::WorldClock;
+use crate::light;
+use crate::light::{LightChannel, MAX_LIGHT};
+use crate::material::Material;
+
+/**
+ * A collection of loaded chunks addressed in world space.
+ */
+#[derive(Default)]
+pub struct World {
+    chunks: HashMap<Coord2DI, Chunk>,
+    pub clock: WorldClock,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            clock: WorldClock::default(),
+        }
+    }
+
+    /**
+     * Splits a world position into the position of its chunk and the position local to that chunk.
+     */
+    pub fn to_local(pos: Coord3DI) -> (Coord2DI, Coord3DI) {
+        let size = CHUNK_SIZE as i32;
+        (
+            Coord2DI::new(pos.x.div_euclid(size), pos.z.div_euclid(size)),
+            Coord3DI::new(pos.x.rem_euclid(size), pos.y, pos.z.rem_euclid(size)),
+        )
+    }
+
+    pub fn to_world(chunk: Coord2DI, local: Coord3DI) -> Coord3DI {
+        let size = CHUNK_SIZE as i32;
+        Coord3DI::new(chunk.x * size + local.x, local.y, chunk.y * size + local.z)
+    }
+
+    /**
+     * Adds a chunk and lights it, along with the borders of the chunks around it.
+     */
+    pub fn insert_chunk(&mut self, chunk: Chunk) {
+        let pos = chunk.pos;
+        self.chunks.insert(pos, chunk);
+        light::light_chunk(self, pos);
+    }
+
+    pub fn get_chunk(&self, pos: Coord2DI) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    pub fn get_chunk_mut(&mut self, pos: Coord2DI) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&pos)
+    }
+
+    pub fn chunks(&self) -> Values<'_, Coord2DI, Chunk> {
+        self.chunks.values()
+    }
+
+    pub fn chunks_mut(&mut self) -> ValuesMut<'_, Coord2DI, Chunk> {
+        self.chunks.values_mut()
+    }
+
+    /**
+     * Generates every missing chunk within `radius` chunks of `center`.
+     */
+    pub fn generate_around(&mut self, generator: &dyn ChunkGenerator, center: Coord2DI, radius: i32) {
+        for x in center.x - radius..=center.x + radius {
+            for z in center.y - radius..=center.y + radius {
+                let pos = Coord2DI::new(x, z);
+                if !self.chunks.contains_key(&pos) {
+                    self.insert_chunk(generator.generate_chunk(pos));
+                }
+            }
+        }
+    }
+
+    pub fn get_block(&self, pos: Coord3DI) -> Option<&Block> {
+        let (chunk, local) = Self::to_local(pos);
+        self.get_chunk(chunk)?.block_at(local.x, local.y, local.z)
+    }
+
+    /**
+     * Sets the block at a world position and updates the light around it. An empty chunk is
+     * created if none is loaded there.
+     */
+    pub fn set_block(&mut self, pos: Coord3DI, material: Option<Material>) {
+        let (chunk, local) = Self::to_local(pos);
+        if !self.chunks.contains_key(&chunk) {
+            if material.is_none() {
+                return;
+            }
+            self.insert_chunk(Chunk::new(chunk));
+        }
+
+        let was_transparent = self.can_hold_light(pos);
+        let was_emission = self.light_emission(pos);
+        self.chunks.get_mut(&chunk).unwrap().set_block(local, material);
+        light::update_block(self, pos, was_transparent, was_emission);
+    }
+
+    /**
+     * Sets many blocks at once, relighting once for all of them instead of after every block.
+     * Empty chunks are created where needed, like `set_block`.
+     */
+    pub fn set_blocks(&mut self, blocks: impl IntoIterator<Item = (Coord3DI, Option<Material>)>) {
+        let mut changed = vec![];
+        for (pos, material) in blocks {
+            let (chunk, local) = Self::to_local(pos);
+            if !self.chunks.contains_key(&chunk) {
+                if material.is_none() {
+                    continue;
+                }
+                self.insert_chunk(Chunk::new(chunk));
+            }
+            self.chunks.get_mut(&chunk).unwrap().set_block(local, material);
+            changed.push(pos);
+        }
+        light::update_blocks(self, &changed);
+    }
+
+    /**
+     * The block light level given off by the block at a position, 0 for air.
+     */
+    pub fn light_emission(&self, pos: Coord3DI) -> u8 {
+        self.get_block(pos).map_or(0, |block| block.material.light_emission())
+    }
+
+    /**
+     * Whether light can pass through a position, which needs a loaded chunk and no opaque block.
+     */
+    pub fn can_hold_light(&self, pos: Coord3DI) -> bool {
+        let (chunk, local) = Self::to_local(pos);
+        match self.get_chunk(chunk) {
+            Some(chunk) => Chunk::in_bounds(local.x, local.y, local.z)
+                && chunk.block_at(local.x, local.y, local.z).is_none_or(|block| !block.material.is_opaque()),
+            None => false,
+        }
+    }
+
+    /**
+     * Returns the light level at a world position. Above the world is open sky,
+     * unloaded chunks and below the world are dark.
+     */
+    pub fn get_light(&self, pos: Coord3DI, channel: LightChannel) -> u8 {
+        if pos.y >= CHUNK_HEIGHT as i32 {
+            return match channel {
+                LightChannel::Sky => MAX_LIGHT,
+                LightChannel::Block => 0,
+            };
+        }
+
+        let (chunk, local) = Self::to_local(pos);
+        self.get_chunk(chunk).map_or(0, |chunk| chunk.get_light(channel, local.x, local.y, local.z))
+    }
+
+    pub fn set_light(&mut self, pos: Coord3DI, channel: LightChannel, level: u8) {
+        let (chunk, local) = Self::to_local(pos);
+        if let Some(chunk) = self.get_chunk_mut(chunk) {
+            chunk.set_light(channel, local.x, local.y, local.z, level);
+        }
+    }
+}